@@ -1,3 +1,7 @@
+#[cfg(feature = "p2p")]
+pub mod p2p;
+#[cfg(feature = "server")]
+pub mod server;
 pub mod traits;
 pub mod types;
 pub mod utils;
@@ -1,19 +1,328 @@
-use ed25519_dalek::{Keypair, Signer, Verifier};
+//! Command line interface for a chain persisted in a JSON file.
+//!
+//! `new-account`, `mint` and `transfer` put a transaction into the pool and
+//! `mine` appends all pooled transactions as a new block. The file is saved
+//! after every mutating command.
+//!
+//! Secret keys of accounts created by `new-account` are kept unencrypted next
+//! to the chain file, in `<file>.keys` readable only by its owner on Unix.
+//! Anyone who can read that file can spend from the accounts.
+//!
+//! Exit codes:
+//! * `0` - success
+//! * `1` - the chain or key file can't be read, parsed or written
+//! * `2` - invalid command line arguments
+//! * `3` - a transaction or block was rejected, or the account is unknown
+//! * `4` - the chain loaded from the file failed validation
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use blockchain::traits::{Hashable, WorldStateRead};
+use blockchain::types::{
+    normalize_account_id, AccountId, Balance, Blockchain, Error, Transaction, TransactionData,
+};
+use blockchain::utils::sign_hash;
+use clap::{Parser, Subcommand};
+use ed25519_dalek::Keypair;
 use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+
+const EXIT_IO: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_REJECTED: i32 = 3;
+const EXIT_INVALID_CHAIN: i32 = 4;
+
+#[derive(Parser)]
+#[clap(about = "Build and inspect a blockchain stored in a JSON file")]
+struct Cli {
+    /// Chain file, created on first use. Secret keys are stored unencrypted in
+    /// `<file>.keys`, keep it private.
+    #[clap(long, default_value = "chain.json")]
+    file: PathBuf,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create an account with a freshly generated keypair
+    NewAccount { id: AccountId },
+    /// Mint initial supply, only allowed before the genesis block is mined
+    Mint { id: AccountId, amount: Balance },
+    /// Transfer funds, signed with the sender's stored key
+    Transfer {
+        from: AccountId,
+        to: AccountId,
+        amount: Balance,
+        /// Burned on top of the amount, higher fees are mined first
+        #[clap(long, default_value = "0")]
+        fee: Balance,
+    },
+    /// Append pooled transactions as a new block
+    Mine,
+    /// Print the balance of an account
+    Balance { id: AccountId },
+}
+
+/// Chain together with hex-encoded keypairs of accounts created by the CLI.
+/// The keys are saved to their own file, see `key_path`.
+#[derive(Default, Serialize, Deserialize)]
+struct State {
+    blockchain: Blockchain,
+    /// Only read from chain files saved before keys got their own file
+    #[serde(default, skip_serializing)]
+    keys: HashMap<AccountId, String>,
+}
 
 pub fn main() {
-    let mut rng = OsRng {};
-    let keypair: Keypair = Keypair::generate(&mut rng);
-    let msg = b"hello world";
+    if let Err((code, error)) = run(Cli::parse()) {
+        eprintln!("Error: {}", error);
+        process::exit(code);
+    }
+}
+
+fn run(cli: Cli) -> Result<(), (i32, Error)> {
+    let mut state = load(&cli.file)?;
+    state
+        .blockchain
+        .validate()
+        .map_err(|error| (EXIT_INVALID_CHAIN, error))?;
+
+    match cli.command {
+        Command::NewAccount { id } => {
+            let id = account_id(&id)?;
+            let keypair = Keypair::generate(&mut OsRng {});
+            let tx = Transaction::new(
+                TransactionData::CreateAccount(id.clone(), keypair.public),
+                None,
+            );
+            submit(&mut state, tx)?;
+            state.keys.insert(id, hex::encode(keypair.to_bytes()));
+        }
+        Command::Mint { id, amount } => {
+            let to = account_id(&id)?;
+            let tx = Transaction::new(TransactionData::MintInitialSupply { to, amount }, None);
+            submit(&mut state, tx)?;
+        }
+        Command::Transfer {
+            from,
+            to,
+            amount,
+            fee,
+        } => {
+            let (from, to) = (account_id(&from)?, account_id(&to)?);
+            let keypair = state
+                .keys
+                .get(&from)
+                .ok_or_else(|| (EXIT_REJECTED, format!("No key stored for {}", from)))
+                .and_then(|key| {
+                    hex::decode(key)
+                        .map_err(|error| error.to_string())
+                        .and_then(|bytes| {
+                            Keypair::from_bytes(&bytes).map_err(|error| error.to_string())
+                        })
+                        .map_err(|error| (EXIT_IO, error))
+                })?;
+            let mut tx =
+                Transaction::new(TransactionData::Transfer { to, amount, fee }, Some(from));
+            tx.add_signature(sign_hash(
+                &keypair,
+                state.blockchain.network_id(),
+                &tx.hash(),
+            ));
+            submit(&mut state, tx)?;
+        }
+        Command::Mine => {
+            state
+                .blockchain
+                .mine_pending_transactions()
+                .map_err(|error| (EXIT_REJECTED, error))?;
+            println!(
+                "Mined block {}",
+                state.blockchain.get_last_block_hash().unwrap_or_default()
+            );
+        }
+        Command::Balance { id } => {
+            let id = account_id(&id)?;
+            let account = state
+                .blockchain
+                .get_account_by_id(&id)
+                .ok_or_else(|| (EXIT_REJECTED, format!("Account not found: {}", id)))?;
+            println!("{}", account.balance());
+            return Ok(());
+        }
+    }
+
+    save(&cli.file, &state)
+}
+
+/// Ids are stored lowercased on chain, so `Alice` and `alice` share one key
+fn account_id(id: &str) -> Result<AccountId, (i32, Error)> {
+    normalize_account_id(id).map_err(|error| (EXIT_USAGE, error))
+}
+
+fn submit(state: &mut State, tx: Transaction) -> Result<(), (i32, Error)> {
+    let hash = tx.hash();
+    let evicted = state
+        .blockchain
+        .add_to_pool(tx)
+        .map_err(|error| (EXIT_REJECTED, error))?;
+    println!("Pooled transaction {}", hash);
+    if let Some(evicted) = evicted {
+        println!("Evicted transaction {}", evicted.hash());
+    }
+    Ok(())
+}
+
+/// Secret keys of the chain file at `path`
+fn key_path(path: &Path) -> PathBuf {
+    let mut key_path = path.as_os_str().to_owned();
+    key_path.push(".keys");
+    PathBuf::from(key_path)
+}
+
+fn load(path: &Path) -> Result<State, (i32, Error)> {
+    if !path.exists() {
+        return Ok(State::default());
+    }
+
+    let mut state: State = read_json(path)?;
+    let key_path = key_path(path);
+    if key_path.exists() {
+        let keys: HashMap<AccountId, String> = read_json(&key_path)?;
+        state.keys.extend(keys);
+    }
+    Ok(state)
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, (i32, Error)> {
+    fs::read_to_string(path)
+        .map_err(|error| error.to_string())
+        .and_then(|json| serde_json::from_str(&json).map_err(|error| error.to_string()))
+        .map_err(|error| (EXIT_IO, format!("Can't load {}: {}", path.display(), error)))
+}
+
+/// Keys are written first, so a saved account never lacks its key
+fn save(path: &Path, state: &State) -> Result<(), (i32, Error)> {
+    let key_path = key_path(path);
+    serde_json::to_string_pretty(&state.keys)
+        .map_err(|error| error.to_string())
+        .and_then(|json| write_private(&key_path, &json).map_err(|error| error.to_string()))
+        .map_err(|error| {
+            (
+                EXIT_IO,
+                format!("Can't save {}: {}", key_path.display(), error),
+            )
+        })?;
+
+    serde_json::to_string_pretty(state)
+        .map_err(|error| error.to_string())
+        .and_then(|json| fs::write(path, json).map_err(|error| error.to_string()))
+        .map_err(|error| (EXIT_IO, format!("Can't save {}: {}", path.display(), error)))
+}
+
+/// Writes a file only its owner can read and write
+fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    // `mode` only applies to new files
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(contents.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain::utils::generate_random_account;
+
+    fn run_command(file: &Path, command: Command) -> Result<(), (i32, Error)> {
+        run(Cli {
+            file: file.to_path_buf(),
+            command,
+        })
+    }
+
+    #[test]
+    fn test_mixed_case_account_id() {
+        let file = std::env::temp_dir().join(format!(
+            "blockchain-cli-test-{}.json",
+            generate_random_account()
+        ));
+        let new_account = |id: &str| Command::NewAccount { id: id.to_string() };
+        run_command(&file, new_account("Alice")).unwrap();
+        run_command(&file, new_account("bob")).unwrap();
+        run_command(
+            &file,
+            Command::Mint {
+                id: "ALICE".to_string(),
+                amount: 100.into(),
+            },
+        )
+        .unwrap();
+        run_command(&file, Command::Mine).unwrap();
+
+        run_command(
+            &file,
+            Command::Transfer {
+                from: "aLiCe".to_string(),
+                to: "Bob".to_string(),
+                amount: 30.into(),
+                fee: 0.into(),
+            },
+        )
+        .unwrap();
+        run_command(&file, Command::Mine).unwrap();
+
+        let state = load(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        fs::remove_file(key_path(&file)).unwrap();
+        let mut ids: Vec<_> = state.keys.keys().collect();
+        ids.sort();
+        assert_eq!(ids, ["alice", "bob"]);
+        let balance = |id: &str| {
+            state
+                .blockchain
+                .get_account_by_id(&id.to_string())
+                .unwrap()
+                .balance()
+        };
+        assert_eq!(balance("alice"), Balance::from(70));
+        assert_eq!(balance("bob"), Balance::from(30));
+    }
 
-    println!("Public key: {:?}", &hex::encode(&keypair.public));
-    println!("Private key: {:?}", &hex::encode(&keypair.secret));
+    #[test]
+    fn test_keys_kept_apart() {
+        let file = std::env::temp_dir().join(format!(
+            "blockchain-cli-test-{}.json",
+            generate_random_account()
+        ));
+        run_command(
+            &file,
+            Command::NewAccount {
+                id: "alice".to_string(),
+            },
+        )
+        .unwrap();
 
-    let signature = keypair.sign(msg);
+        let chain = fs::read_to_string(&file).unwrap();
+        let keys = fs::read_to_string(key_path(&file)).unwrap();
+        #[cfg(unix)]
+        let mode = std::os::unix::fs::PermissionsExt::mode(
+            &fs::metadata(key_path(&file)).unwrap().permissions(),
+        );
+        fs::remove_file(&file).unwrap();
+        fs::remove_file(key_path(&file)).unwrap();
 
-    assert!(keypair.public.verify(msg, &signature).is_ok());
-    assert!(keypair
-        .public
-        .verify(b"another message", &signature)
-        .is_err());
+        assert!(!chain.contains("keys"));
+        assert!(keys.contains("alice"));
+        #[cfg(unix)]
+        assert_eq!(mode & 0o777, 0o600);
+    }
 }
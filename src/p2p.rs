@@ -0,0 +1,180 @@
+//! Block gossip between nodes over TCP.
+//!
+//! Every message is a JSON-encoded [`Message`] prefixed with its length as a
+//! big-endian `u32`. A peer answers `NewBlock` with `Accepted` or `Rejected`
+//! and `GetChain` with `Chain`.
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::{Block, Blockchain, Error};
+
+/// Frames above this size are refused before allocating a buffer for them
+pub const MAX_FRAME_LEN: u32 = 16 * 1024 * 1024;
+/// How long to wait on a peer to connect, send or receive before giving up
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Message {
+    NewBlock(Block),
+    GetChain,
+    Accepted,
+    Rejected(Error),
+    /// Blocks oldest first
+    Chain(Vec<Block>),
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Message) -> Result<(), Error> {
+    let frame = serde_json::to_vec(message).map_err(|error| error.to_string())?;
+    let len = u32::try_from(frame.len())
+        .ok()
+        .filter(|len| *len <= MAX_FRAME_LEN)
+        .ok_or_else(|| format!("Frame is too large: {} bytes", frame.len()))?;
+
+    writer
+        .write_all(&len.to_be_bytes())
+        .and_then(|_| writer.write_all(&frame))
+        .and_then(|_| writer.flush())
+        .map_err(|error| error.to_string())
+}
+
+pub fn read_message<R: Read>(reader: &mut R) -> Result<Message, Error> {
+    let mut len = [0u8; 4];
+    reader
+        .read_exact(&mut len)
+        .map_err(|error| error.to_string())?;
+    let len = u32::from_be_bytes(len);
+    if len > MAX_FRAME_LEN {
+        return Err(format!("Frame is too large: {} bytes", len));
+    }
+
+    let mut frame = vec![0u8; len as usize];
+    reader
+        .read_exact(&mut frame)
+        .map_err(|error| error.to_string())?;
+    serde_json::from_slice(&frame).map_err(|error| error.to_string())
+}
+
+/// Sends a message to a peer and waits for its reply, at most `TIMEOUT` for
+/// each step
+pub fn request(peer: SocketAddr, message: &Message) -> Result<Message, Error> {
+    let mut stream =
+        TcpStream::connect_timeout(&peer, TIMEOUT).map_err(|error| error.to_string())?;
+    set_timeouts(&stream)?;
+    write_message(&mut stream, message)?;
+    read_message(&mut stream)
+}
+
+fn set_timeouts(stream: &TcpStream) -> Result<(), Error> {
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(TIMEOUT)))
+        .map_err(|error| error.to_string())
+}
+
+pub struct Node {
+    listener: TcpListener,
+    blockchain: Arc<Mutex<Blockchain>>,
+    peers: Mutex<Vec<SocketAddr>>,
+}
+
+impl Node {
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        blockchain: Arc<Mutex<Blockchain>>,
+    ) -> Result<Self, Error> {
+        let listener = TcpListener::bind(addr).map_err(|error| error.to_string())?;
+        Ok(Self {
+            listener,
+            blockchain,
+            peers: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.listener
+            .local_addr()
+            .map_err(|error| error.to_string())
+    }
+
+    pub fn add_peer(&self, peer: SocketAddr) {
+        self.peers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(peer);
+    }
+
+    /// Serves every connection on its own thread, so a slow or silent peer
+    /// only holds up itself. A misbehaving peer only loses its own connection,
+    /// the listener keeps running.
+    pub fn run(&self) {
+        for stream in self.listener.incoming().flatten() {
+            let blockchain = self.blockchain.clone();
+            thread::spawn(move || serve(&blockchain, stream));
+        }
+    }
+
+    /// Mines pooled transactions and announces the new block to all peers.
+    /// Returns the replies of the peers that could be reached.
+    pub fn mine(&self) -> Result<Vec<(SocketAddr, Message)>, Error> {
+        let block = {
+            let mut blockchain = self
+                .blockchain
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            blockchain.mine_pending_transactions()?;
+            blockchain
+                .blocks
+                .head()
+                .cloned()
+                .ok_or_else(|| "Mined block is missing.".to_string())?
+        };
+
+        let peers = self
+            .peers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        let message = Message::NewBlock(block);
+        Ok(peers
+            .into_iter()
+            .filter_map(|peer| Some((peer, request(peer, &message).ok()?)))
+            .collect())
+    }
+}
+
+/// Answers messages until the peer hangs up or stays quiet for `TIMEOUT`
+fn serve(blockchain: &Mutex<Blockchain>, mut stream: TcpStream) {
+    if set_timeouts(&stream).is_err() {
+        return;
+    }
+    while let Ok(message) = read_message(&mut stream) {
+        let reply = handle(blockchain, message);
+        if write_message(&mut stream, &reply).is_err() {
+            break;
+        }
+    }
+}
+
+fn handle(blockchain: &Mutex<Blockchain>, message: Message) -> Message {
+    let mut blockchain = blockchain
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    match message {
+        Message::NewBlock(block) => {
+            if block.prev_hash != blockchain.get_last_block_hash() {
+                return Message::Rejected("Block doesn't extend the head.".to_string());
+            }
+            match blockchain.append_block(block) {
+                Ok(_) => Message::Accepted,
+                Err(error) => Message::Rejected(error),
+            }
+        }
+        Message::GetChain => Message::Chain(blockchain.blocks.iter_forward().cloned().collect()),
+        _ => Message::Rejected("Unexpected message.".to_string()),
+    }
+}
@@ -0,0 +1,152 @@
+//! HTTP API over a shared blockchain.
+//!
+//! * `GET /height` - number of blocks since genesis
+//! * `GET /block/{hash}` - block by hash
+//! * `GET /account/{id}` - account balance and type
+//! * `POST /tx` - put a JSON-encoded signed transaction into the pool
+use std::io::Read;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response};
+
+use crate::traits::{Hashable, WorldStateRead};
+use crate::types::{
+    normalize_account_id, AccountType, Balance, Blockchain, Error, Hash, Transaction,
+};
+
+/// Largest request body in bytes, larger ones are refused with 413
+pub const MAX_BODY: usize = 64 * 1024;
+
+#[derive(Serialize)]
+struct HeightResponse {
+    height: usize,
+}
+
+#[derive(Serialize)]
+struct AccountResponse<'a> {
+    balance: Balance,
+    account_type: &'a AccountType,
+}
+
+#[derive(Serialize)]
+struct TransactionResponse {
+    hash: Hash,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: Error,
+}
+
+pub struct Server {
+    http: tiny_http::Server,
+    blockchain: Arc<Mutex<Blockchain>>,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        blockchain: Arc<Mutex<Blockchain>>,
+    ) -> Result<Self, Error> {
+        let http = tiny_http::Server::http(addr).map_err(|error| error.to_string())?;
+        Ok(Self { http, blockchain })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Serves requests until the listener fails
+    pub fn run(&self) {
+        for request in self.http.incoming_requests() {
+            self.handle(request);
+        }
+    }
+
+    fn handle(&self, mut request: Request) {
+        // One byte past the limit is enough to tell the body is too large
+        let mut body = Vec::new();
+        let read = request
+            .as_reader()
+            .take(MAX_BODY as u64 + 1)
+            .read_to_end(&mut body);
+        let (status, json) = match read {
+            Err(error) => error_response(400, error.to_string()),
+            Ok(_) if body.len() > MAX_BODY => error_response(
+                413,
+                format!("Request body is larger than {} bytes", MAX_BODY),
+            ),
+            Ok(_) => match String::from_utf8(body) {
+                Ok(body) => self.route(request.method(), request.url(), &body),
+                Err(error) => error_response(400, error.to_string()),
+            },
+        };
+
+        let response = Response::from_string(json)
+            .with_status_code(status)
+            .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+        // Nothing to do if the client has already gone
+        let _ = request.respond(response);
+    }
+
+    fn route(&self, method: &Method, url: &str, body: &str) -> (u16, String) {
+        let mut blockchain = self
+            .blockchain
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let path = url.split('?').next().unwrap_or_default();
+        let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+
+        match (method, segments.as_slice()) {
+            (Method::Get, ["height"]) => json_response(
+                200,
+                &HeightResponse {
+                    height: blockchain.height(),
+                },
+            ),
+            (Method::Get, ["block", hash]) => match blockchain.get_block_by_hash(&hash.to_string())
+            {
+                Some(block) => json_response(200, block),
+                None => error_response(404, format!("Block not found: {}", hash)),
+            },
+            (Method::Get, ["account", id]) => {
+                match normalize_account_id(id).map(|id| blockchain.get_account_by_id(&id)) {
+                    Err(error) => error_response(400, error),
+                    Ok(Some(account)) => json_response(
+                        200,
+                        &AccountResponse {
+                            balance: account.balance(),
+                            account_type: account.account_type(),
+                        },
+                    ),
+                    Ok(None) => error_response(404, format!("Account not found: {}", id)),
+                }
+            }
+            (Method::Post, ["tx"]) => match serde_json::from_str::<Transaction>(body) {
+                Ok(tx) => {
+                    let hash = tx.hash();
+                    match blockchain.add_to_pool(tx) {
+                        Ok(_) => json_response(200, &TransactionResponse { hash }),
+                        Err(error) => error_response(400, error),
+                    }
+                }
+                Err(error) => error_response(400, error.to_string()),
+            },
+            _ => error_response(404, format!("Not found: {} {}", method, path)),
+        }
+    }
+}
+
+fn json_response<T: Serialize>(status: u16, value: &T) -> (u16, String) {
+    match serde_json::to_string(value) {
+        Ok(json) => (status, json),
+        Err(error) => error_response(500, error.to_string()),
+    }
+}
+
+fn error_response(status: u16, error: Error) -> (u16, String) {
+    let json = serde_json::to_string(&ErrorResponse { error }).unwrap_or_default();
+    (status, json)
+}
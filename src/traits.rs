@@ -1,9 +1,56 @@
-use crate::types::{Account, AccountId, AccountType, Hash};
+use crate::types::{Account, AccountId, AccountType, Balance, Hash};
+use std::fmt::Debug;
+
 use ed25519_dalek::PublicKey;
 
-pub trait WorldState {
+/// Read access to accounts, enough for queries that shouldn't need a mutable state
+pub trait WorldStateRead {
     fn get_account_ids(&self) -> Vec<AccountId>;
     fn get_account_by_id(&self, id: &AccountId) -> Option<&Account>;
+
+    fn account_exists(&self, id: &AccountId) -> bool {
+        self.get_account_by_id(id).is_some()
+    }
+
+    /// Part of every signed message, see `utils::signing_message`
+    fn network_id(&self) -> &str;
+
+    /// Account allowed to freeze and unfreeze other accounts, if any
+    fn admin(&self) -> Option<&AccountId> {
+        None
+    }
+
+    /// Whether new account ids must be derived from their public keys
+    fn requires_derived_account_ids(&self) -> bool {
+        false
+    }
+
+    /// Smallest non-zero amount a transfer may move
+    fn min_transfer(&self) -> Balance {
+        Balance::ZERO
+    }
+
+    /// Burned from the funder of every account created outside genesis
+    fn account_creation_fee(&self) -> Balance {
+        Balance::ZERO
+    }
+
+    /// Limit for the total supply, if any
+    fn max_supply(&self) -> Option<Balance> {
+        None
+    }
+
+    fn total_supply(&self) -> Balance {
+        self.get_account_ids()
+            .iter()
+            .filter_map(|id| self.get_account_by_id(id))
+            .fold(Balance::ZERO, |total, account| {
+                total.saturating_add(account.balance)
+            })
+    }
+}
+
+pub trait WorldState: WorldStateRead {
     fn get_account_by_id_mut(&mut self, id: &AccountId) -> Option<&mut Account>;
     fn create_account(
         &mut self,
@@ -13,10 +60,18 @@ pub trait WorldState {
     ) -> Result<(), String>;
 }
 
+/// Hash function of a chain. Blocks and transactions of one chain must share it,
+/// otherwise their stored hashes won't verify.
+pub trait Hasher: Debug + Default + Clone {
+    fn digest(&self, data: &[u8]) -> Hash;
+}
+
 pub trait Hashable {
     fn hash(&self) -> Hash;
 }
 
 pub trait Verifiable: Hashable {
-    fn verify(&self) -> bool;
+    /// Checks that need no world state. Signatures are checked as made for the
+    /// chain with `network_id`, see `WorldStateRead::network_id`.
+    fn verify(&self, network_id: &str) -> bool;
 }
@@ -1,25 +1,190 @@
-use crate::types::Balance;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::types::{AccountId, Balance, Error};
+use crate::utils::HexKey;
 use ed25519_dalek::PublicKey;
+use serde::{Deserialize, Serialize};
+
+pub const MAX_ACCOUNT_ID_LEN: usize = 64;
+/// Longest metadata value in bytes
+pub const MAX_METADATA_VALUE_LEN: usize = 256;
+/// Most bytes all metadata keys and values of an account may take together
+pub const MAX_METADATA_LEN: usize = 1024;
+
+/// Trims and lowercases the id, then checks its length and that it only has
+/// ascii letters, digits, `_`, `-` and `.`
+pub fn normalize_account_id(account_id: &str) -> Result<AccountId, Error> {
+    let normalized = account_id.trim().to_lowercase();
+
+    if normalized.is_empty() || normalized.len() > MAX_ACCOUNT_ID_LEN {
+        return Err(format!(
+            "Invalid account id: {:?} must be 1 to {} characters long",
+            account_id, MAX_ACCOUNT_ID_LEN
+        ));
+    }
+    if !normalized
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    {
+        return Err(format!(
+            "Invalid account id: {:?} has illegal characters",
+            account_id
+        ));
+    }
+
+    Ok(normalized)
+}
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub enum AccountType {
     User,
     Contract,
+    /// Spending needs valid signatures from at least `threshold` of the signers
+    Multisig {
+        signers: Vec<PublicKey>,
+        threshold: usize,
+    },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Account {
     pub(crate) account_type: AccountType,
     pub(crate) balance: Balance,
     pub(crate) public_key: PublicKey,
+    /// Frozen accounts can receive funds but can't send them
+    #[serde(default)]
+    pub(crate) frozen: bool,
+    /// Whether the account has received its initial supply
+    #[serde(default)]
+    pub(crate) minted: bool,
+    /// Labels set by the owner, e.g. a display name under `name`
+    #[serde(default)]
+    pub(crate) metadata: HashMap<String, String>,
+}
+
+/// Keys are printed as hex
+impl fmt::Debug for AccountType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountType::User => f.write_str("User"),
+            AccountType::Contract => f.write_str("Contract"),
+            AccountType::Multisig { signers, threshold } => f
+                .debug_struct("Multisig")
+                .field("signers", &signers.iter().map(HexKey).collect::<Vec<_>>())
+                .field("threshold", threshold)
+                .finish(),
+        }
+    }
+}
+
+/// Keys are printed as hex
+impl fmt::Debug for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Account")
+            .field("account_type", &self.account_type)
+            .field("balance", &self.balance)
+            .field("public_key", &HexKey(&self.public_key))
+            .field("frozen", &self.frozen)
+            .field("minted", &self.minted)
+            .field("metadata", &self.metadata)
+            .finish()
+    }
 }
 
 impl Account {
     pub fn new(account_type: AccountType, public_key: PublicKey) -> Self {
         Self {
             account_type,
-            balance: 0,
+            balance: Balance::ZERO,
             public_key,
+            frozen: false,
+            minted: false,
+            metadata: HashMap::new(),
         }
     }
+
+    pub fn account_type(&self) -> &AccountType {
+        &self.account_type
+    }
+
+    pub fn balance(&self) -> Balance {
+        self.balance
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.metadata
+    }
+
+    /// Total bytes of metadata keys and values
+    pub(crate) fn metadata_len(&self) -> usize {
+        self.metadata
+            .iter()
+            .map(|(key, value)| key.len() + value.len())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::Keypair;
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn test_debug_hex_key() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let account = Account::new(AccountType::User, keypair.public);
+        let debug = format!("{:?}", account);
+
+        assert!(debug.contains(&format!("public_key: {}", hex::encode(keypair.public))));
+        let multisig = AccountType::Multisig {
+            signers: vec![keypair.public],
+            threshold: 1,
+        };
+        assert_eq!(
+            format!("{:?}", multisig),
+            format!(
+                "Multisig {{ signers: [{}], threshold: 1 }}",
+                hex::encode(keypair.public)
+            )
+        );
+    }
+
+    #[test]
+    fn test_normalize_account_id() {
+        assert_eq!(normalize_account_id(" Alice\t"), Ok("alice".to_string()));
+        assert_eq!(
+            normalize_account_id("bob_1.test-net"),
+            Ok("bob_1.test-net".to_string())
+        );
+    }
+
+    #[test]
+    fn test_normalize_account_id_too_long() {
+        assert!(normalize_account_id(&"a".repeat(MAX_ACCOUNT_ID_LEN)).is_ok());
+        assert_eq!(
+            normalize_account_id(&"a".repeat(MAX_ACCOUNT_ID_LEN + 1)),
+            Err(format!(
+                "Invalid account id: {:?} must be 1 to 64 characters long",
+                "a".repeat(MAX_ACCOUNT_ID_LEN + 1)
+            ))
+        );
+        assert!(normalize_account_id("   ").is_err());
+    }
+
+    #[test]
+    fn test_normalize_account_id_illegal_chars() {
+        assert_eq!(
+            normalize_account_id("alice smith"),
+            Err("Invalid account id: \"alice smith\" has illegal characters".to_string())
+        );
+        assert!(normalize_account_id("alice/../bob").is_err());
+        assert!(normalize_account_id("алиса").is_err());
+    }
 }
@@ -0,0 +1,144 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Amount of coins in base units. Arithmetic is checked or saturating, it never
+/// wraps silently.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Balance(u128);
+
+/// Result of an addition above `Balance::MAX` or a subtraction below zero
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BalanceOverflow;
+
+impl fmt::Display for BalanceOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Balance overflow.")
+    }
+}
+
+impl From<BalanceOverflow> for String {
+    fn from(error: BalanceOverflow) -> Self {
+        error.to_string()
+    }
+}
+
+impl Balance {
+    pub const ZERO: Balance = Balance(0);
+    pub const MAX: Balance = Balance(u128::MAX);
+
+    pub const fn new(value: u128) -> Self {
+        Balance(value)
+    }
+
+    pub const fn value(self) -> u128 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Balance) -> Result<Balance, BalanceOverflow> {
+        self.0
+            .checked_add(other.0)
+            .map(Balance)
+            .ok_or(BalanceOverflow)
+    }
+
+    pub fn checked_sub(self, other: Balance) -> Result<Balance, BalanceOverflow> {
+        self.0
+            .checked_sub(other.0)
+            .map(Balance)
+            .ok_or(BalanceOverflow)
+    }
+
+    pub fn saturating_add(self, other: Balance) -> Balance {
+        Balance(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Balance) -> Balance {
+        Balance(self.0.saturating_sub(other.0))
+    }
+}
+
+impl From<u128> for Balance {
+    fn from(value: u128) -> Self {
+        Balance(value)
+    }
+}
+
+impl From<Balance> for u128 {
+    fn from(balance: Balance) -> Self {
+        balance.0
+    }
+}
+
+impl PartialEq<u128> for Balance {
+    fn eq(&self, other: &u128) -> bool {
+        self.0 == *other
+    }
+}
+
+/// Printed as the plain integer. Transaction hashes are made from the Debug
+/// output, so it has to stay the same as for a bare `u128`.
+impl fmt::Debug for Balance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for Balance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl FromStr for Balance {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Balance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checked_add_overflow() {
+        assert_eq!(
+            Balance::new(1).checked_add(Balance::new(2)),
+            Ok(Balance::new(3))
+        );
+        assert_eq!(
+            Balance::MAX.checked_add(Balance::new(1)),
+            Err(BalanceOverflow)
+        );
+        assert_eq!(Balance::MAX.saturating_add(Balance::new(1)), Balance::MAX);
+    }
+
+    #[test]
+    fn test_checked_sub_underflow() {
+        assert_eq!(
+            Balance::new(3).checked_sub(Balance::new(2)),
+            Ok(Balance::new(1))
+        );
+        assert_eq!(
+            Balance::new(1).checked_sub(Balance::new(2)),
+            Err(BalanceOverflow)
+        );
+        assert_eq!(
+            Balance::new(1).saturating_sub(Balance::new(2)),
+            Balance::ZERO
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(Balance::new(150).to_string(), "150");
+        assert_eq!(format!("{:?}", Balance::new(150)), "150");
+        assert_eq!("150".parse(), Ok(Balance::new(150)));
+        assert_eq!(serde_json::to_string(&Balance::new(150)).unwrap(), "150");
+        assert_eq!(String::from(BalanceOverflow), "Balance overflow.");
+    }
+}
@@ -1,54 +1,342 @@
-use blake2::digest::FixedOutput;
-use blake2::{Blake2s, Digest};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
 
-use crate::traits::{Hashable, Verifiable};
-use crate::types::{Hash, Transaction};
+use bincode::Options;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Default, Clone)]
-pub struct Block {
+use crate::traits::{Hashable, Hasher, Verifiable};
+use crate::types::{Blake2sHasher, Error, Hash, Timestamp, Transaction};
+use crate::utils::{leading_zero_bits, short_hex};
+
+/// Nonces a `mine_parallel` worker tries before checking whether another one
+/// has already finished
+pub const MINING_BATCH: u128 = 1024;
+
+/// Longest `miner_note` a block can carry, in bytes
+pub const MAX_MINER_NOTE_LEN: usize = 64;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(bound(serialize = "", deserialize = "H: Default"))]
+pub struct Block<H = Blake2sHasher> {
     nonce: u128,
+    #[serde(default)]
+    timestamp: Timestamp,
+    #[serde(default)]
+    pub(crate) is_genesis: bool,
+    /// `Blockchain::state_hash` after the block's transactions, required except for genesis
+    #[serde(default)]
+    pub(crate) state_root: Option<Hash>,
+    /// Free-form tag chosen by the miner, at most `MAX_MINER_NOTE_LEN` bytes
+    #[serde(default)]
+    pub(crate) miner_note: Vec<u8>,
     pub(crate) hash: Option<Hash>,
     pub(crate) prev_hash: Option<Hash>,
-    pub(crate) transactions: Vec<Transaction>,
+    pub(crate) transactions: Vec<Transaction<H>>,
+    #[serde(skip)]
+    hasher: H,
 }
 
-impl Hashable for Block {
+impl<H: Hasher> Hashable for Block<H> {
     fn hash(&self) -> Hash {
-        let mut hasher = Blake2s::new();
-
-        hasher.update(format!("{:?}", (self.prev_hash.clone(), self.nonce)).as_bytes());
+        // Fields added to blocks are hashed only when set, so blocks that leave
+        // them as older blocks had them keep their hashes. A genesis marker is
+        // what older blocks implied when it matches the missing `prev_hash`.
+        let implied_genesis = self.is_genesis == self.prev_hash.is_none();
+        let header = match (self.timestamp, implied_genesis) {
+            (0, true) => format!("{:?}", (self.prev_hash.clone(), self.nonce)),
+            (timestamp, true) => format!("{:?}", (self.prev_hash.clone(), self.nonce, timestamp)),
+            (timestamp, false) => format!(
+                "{:?}",
+                (
+                    self.prev_hash.clone(),
+                    self.nonce,
+                    timestamp,
+                    self.is_genesis
+                )
+            ),
+        };
+        let mut data = match (&self.state_root, self.miner_note.is_empty()) {
+            (None, true) => header,
+            (Some(state_root), true) => format!("{:?}", (header, state_root)),
+            (state_root, false) => format!("{:?}", (header, state_root, &self.miner_note)),
+        }
+        .into_bytes();
         for tx in self.transactions.iter() {
-            hasher.update(tx.hash())
+            data.extend(tx.hash().into_bytes());
         }
 
-        hex::encode(hasher.finalize_fixed())
+        self.hasher.digest(&data)
     }
 }
 
-impl Verifiable for Block {
-    fn verify(&self) -> bool {
-        matches!(&self.hash, Some(hash) if hash == &self.hash())
+/// Blocks are equal when their computed hashes are
+impl<H: Hasher> PartialEq for Block<H> {
+    fn eq(&self, other: &Self) -> bool {
+        Hashable::hash(self) == Hashable::hash(other)
+    }
+}
+
+impl<H: Hasher> Eq for Block<H> {}
+
+impl<H: Hasher> std::hash::Hash for Block<H> {
+    fn hash<S: std::hash::Hasher>(&self, state: &mut S) {
+        Hashable::hash(self).hash(state)
+    }
+}
+
+impl<H: Hasher> fmt::Display for Block<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Block {} prev {}, {} txs, timestamp {}",
+            short_hex(&self.hash()),
+            self.prev_hash
+                .as_deref()
+                .map_or("none".to_string(), short_hex),
+            self.transactions.len(),
+            self.timestamp
+        )
+    }
+}
+
+/// Block without its transactions, enough to follow the chain and its work
+/// before downloading bodies. The hash can only be recomputed with the body.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHeader {
+    pub prev_hash: Option<Hash>,
+    pub state_root: Option<Hash>,
+    pub nonce: u128,
+    pub timestamp: Timestamp,
+    pub is_genesis: bool,
+    pub hash: Hash,
+}
+
+impl BlockHeader {
+    pub fn work(&self) -> u128 {
+        1u128
+            .checked_shl(leading_zero_bits(&self.hash))
+            .unwrap_or(u128::MAX)
+    }
+
+    /// Checks that every header, oldest first, points to the one before it
+    pub fn check_links(headers: &[BlockHeader]) -> Result<(), Error> {
+        for (i, pair) in headers.windows(2).enumerate() {
+            if pair[1].prev_hash.as_ref() != Some(&pair[0].hash) {
+                return Err(format!("Header {} doesn't link to header {}", i + 1, i));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockVerifyError {
+    NotHashed,
+    HashMismatch { stored: Hash, computed: Hash },
+}
+
+impl fmt::Display for BlockVerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockVerifyError::NotHashed => write!(f, "Block is not hashed"),
+            BlockVerifyError::HashMismatch { stored, computed } => write!(
+                f,
+                "Block hash mismatch: stored {}, computed {}",
+                stored, computed
+            ),
+        }
+    }
+}
+
+/// Blocks carry no signatures of their own, only their hash is checked
+impl<H: Hasher> Verifiable for Block<H> {
+    fn verify(&self, _network_id: &str) -> bool {
+        self.verify_detailed().is_ok()
     }
 }
 
 impl Block {
+    /// Block without `prev_hash` is a genesis block
     pub fn new(prev_hash: Option<Hash>) -> Self {
+        Self::with_hasher(prev_hash, Blake2sHasher)
+    }
+
+    pub fn genesis() -> Self {
+        Self::new(None)
+    }
+}
+
+impl<H: Hasher> Block<H> {
+    pub fn with_hasher(prev_hash: Option<Hash>, hasher: H) -> Self {
         Block {
+            is_genesis: prev_hash.is_none(),
             prev_hash,
+            hasher,
             ..Default::default()
         }
     }
 
+    /// Genesis status is part of the hash, so it can't change with the block's position
+    pub fn is_genesis(&self) -> bool {
+        self.is_genesis
+    }
+
     pub fn set_nonce(&mut self, nonce: u128) {
         self.nonce = nonce;
         self.update_hash();
     }
 
-    pub fn add_transaction(&mut self, tx: Transaction) {
+    pub fn set_timestamp(&mut self, timestamp: Timestamp) {
+        self.timestamp = timestamp;
+        self.update_hash();
+    }
+
+    pub fn timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            prev_hash: self.prev_hash.clone(),
+            state_root: self.state_root.clone(),
+            nonce: self.nonce,
+            timestamp: self.timestamp,
+            is_genesis: self.is_genesis,
+            hash: self.hash(),
+        }
+    }
+
+    pub fn set_state_root(&mut self, state_root: Hash) {
+        self.state_root = Some(state_root);
+        self.update_hash();
+    }
+
+    pub fn state_root(&self) -> Option<&Hash> {
+        self.state_root.as_ref()
+    }
+
+    /// Refuses notes longer than `MAX_MINER_NOTE_LEN`, the block is left unchanged
+    pub fn set_miner_note(&mut self, note: Vec<u8>) -> Result<(), Error> {
+        if note.len() > MAX_MINER_NOTE_LEN {
+            return Err(format!(
+                "Miner note is too long: {} > {}",
+                note.len(),
+                MAX_MINER_NOTE_LEN
+            ));
+        }
+        self.miner_note = note;
+        self.update_hash();
+        Ok(())
+    }
+
+    pub fn miner_note(&self) -> &[u8] {
+        &self.miner_note
+    }
+
+    pub fn add_transaction(&mut self, tx: Transaction<H>) {
         self.transactions.push(tx);
         self.update_hash();
     }
 
+    pub fn verify_detailed(&self) -> Result<(), BlockVerifyError> {
+        let stored = self.hash.clone().ok_or(BlockVerifyError::NotHashed)?;
+        let computed = self.hash();
+        if stored != computed {
+            return Err(BlockVerifyError::HashMismatch { stored, computed });
+        }
+        Ok(())
+    }
+
+    /// Expected number of hash attempts needed to find this block's hash,
+    /// estimated from its leading zero bits
+    pub fn work(&self) -> u128 {
+        1u128
+            .checked_shl(leading_zero_bits(&self.hash()))
+            .unwrap_or(u128::MAX)
+    }
+
+    /// Whether the hash starts with at least `difficulty` zero bits
+    pub fn meets_difficulty(&self, difficulty: u32) -> bool {
+        leading_zero_bits(&self.hash()) >= difficulty
+    }
+
+    /// Tries nonces from `start` up to but excluding `end` and keeps the first one
+    /// meeting the difficulty. The nonce is left unchanged when none does, so
+    /// workers can each search their own slice of the nonce space.
+    pub fn mine_in_range(&mut self, difficulty: u32, start: u128, end: u128) -> bool {
+        let nonce = self.nonce;
+        for candidate in start..end {
+            self.nonce = candidate;
+            if self.meets_difficulty(difficulty) {
+                self.update_hash();
+                return true;
+            }
+        }
+        self.nonce = nonce;
+        false
+    }
+
+    /// Mines with `threads` workers. Worker `i` searches batches `i`,
+    /// `i + threads`, ... of `MINING_BATCH` nonces, so their ranges never overlap,
+    /// and all of them stop once any finds a nonce.
+    pub fn mine_parallel(&mut self, difficulty: u32, threads: usize)
+    where
+        H: Send + Sync,
+    {
+        let threads = threads.max(1) as u128;
+        let found = AtomicBool::new(false);
+        let nonce = Mutex::new(None);
+
+        thread::scope(|scope| {
+            for worker in 0..threads {
+                let mut block = self.clone();
+                let (found, nonce) = (&found, &nonce);
+                scope.spawn(move || {
+                    let mut batch = worker;
+                    while !found.load(Ordering::Relaxed) {
+                        let start = batch.saturating_mul(MINING_BATCH);
+                        let end = start.saturating_add(MINING_BATCH);
+                        if start == end {
+                            break;
+                        }
+                        if block.mine_in_range(difficulty, start, end) {
+                            nonce.lock().unwrap().get_or_insert(block.nonce);
+                            found.store(true, Ordering::Relaxed);
+                        }
+                        batch += threads;
+                    }
+                });
+            }
+        });
+
+        if let Some(nonce) = nonce.into_inner().unwrap() {
+            self.set_nonce(nonce);
+        }
+    }
+
+    /// Compact binary encoding, keys and signatures are stored as raw bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Block is always serializable")
+    }
+
+    /// Length of `to_bytes` without encoding the block
+    pub fn size_bytes(&self) -> usize {
+        bincode::serialized_size(self).expect("Block is always serializable") as usize
+    }
+
+    /// Decodes `to_bytes` output. Blocks come from peers, so any malformed input
+    /// is an error and never a panic. Length prefixes can't claim more bytes
+    /// than the input has, and trailing bytes are refused.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .with_limit(bytes.len() as u64)
+            .deserialize(bytes)
+            .map_err(|error| error.to_string())
+    }
+
     pub fn transactions_len(&self) -> usize {
         self.transactions.len()
     }
@@ -61,7 +349,11 @@ impl Block {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::{TransactionBuilder, TransactionData};
     use crate::utils::create_account_tx;
+    use ed25519_dalek::Keypair;
+    use rand::rngs::StdRng;
+    use rand::{Rng, RngCore, SeedableRng};
 
     #[test]
     fn test_couple_blocks() {
@@ -91,6 +383,22 @@ mod tests {
         assert_eq!(block.transactions_len(), 2);
     }
 
+    #[test]
+    fn test_block_eq() {
+        let mut block = Block::new(None);
+        block.add_transaction(create_account_tx("alice".to_string()));
+        let mut other = block.clone();
+        other.set_nonce(1);
+
+        assert_eq!(block, block.clone());
+        assert_ne!(block, other);
+
+        let set = vec![block.clone(), block.clone(), other]
+            .into_iter()
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(set.len(), 2);
+    }
+
     #[test]
     fn test_hash() {
         let mut block = Block::new(None);
@@ -108,7 +416,7 @@ mod tests {
         let mut block = Block::new(None);
         block.set_nonce(1);
 
-        assert!(block.verify());
+        assert!(block.verify(""));
     }
 
     #[test]
@@ -117,6 +425,160 @@ mod tests {
         block.set_nonce(1);
         block.nonce = 2;
 
-        assert!(!block.verify());
+        assert!(!block.verify(""));
+    }
+
+    #[test]
+    fn test_block_verify_not_hashed() {
+        let block = Block::new(None);
+
+        assert!(!block.verify(""));
+        assert_eq!(block.verify_detailed(), Err(BlockVerifyError::NotHashed));
+    }
+
+    #[test]
+    fn test_block_verify_hash_mismatch() {
+        let mut block = Block::new(None);
+        block.set_nonce(1);
+        let stored = block.hash();
+        block.nonce = 2;
+
+        assert_eq!(
+            block.verify_detailed(),
+            Err(BlockVerifyError::HashMismatch {
+                stored,
+                computed: block.hash()
+            })
+        );
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mut block = Block::new(Some("prev".to_string()));
+        block.add_transaction(create_account_tx("alice".to_string()));
+        block.add_transaction(
+            TransactionBuilder::new(TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount: 10.into(),
+                fee: 0.into(),
+            })
+            .from("alice".to_string())
+            .sign(&keypair, ""),
+        );
+        block.set_nonce(7);
+
+        let bytes = block.to_bytes();
+        let decoded = Block::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, block);
+        assert_eq!(decoded.hash, block.hash);
+        assert!(decoded.verify(""));
+        assert_eq!(
+            decoded.transactions[1].signature(),
+            block.transactions[1].signature()
+        );
+        assert!(bytes.len() < serde_json::to_vec(&block).unwrap().len());
+
+        assert!(Block::<Blake2sHasher>::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_mine_in_range() {
+        let mut block = Block::new(Some("prev".to_string()));
+        block.add_transaction(create_account_tx("alice".to_string()));
+        let hash = block.hash.clone();
+
+        assert!(!block.mine_in_range(1, 5, 5));
+        assert!(!block.mine_in_range(128, 0, 4));
+        assert_eq!(block.nonce, 0);
+        assert_eq!(block.hash, hash);
+
+        assert!(block.mine_in_range(1, 0, 1000));
+        assert!(block.nonce < 1000);
+        assert!(block.meets_difficulty(1));
+        assert!(block.verify(""));
+    }
+
+    #[test]
+    fn test_mine_parallel() {
+        let mut block = Block::new(Some("prev".to_string()));
+        block.add_transaction(create_account_tx("alice".to_string()));
+
+        block.mine_parallel(2, 4);
+        assert!(block.meets_difficulty(2));
+        assert!(block.verify(""));
+
+        let mut single = block.clone();
+        single.set_nonce(0);
+        assert!(single.mine_in_range(2, block.nonce, block.nonce + 1));
+        assert_eq!(single.hash, block.hash);
+    }
+
+    #[test]
+    fn test_miner_note() {
+        let mut block = Block::new(Some("prev".to_string()));
+        block.add_transaction(create_account_tx("alice".to_string()));
+        let hash = block.hash();
+        assert!(block.miner_note().is_empty());
+
+        block.set_miner_note(b"/my-pool/".to_vec()).unwrap();
+        assert_ne!(block.hash(), hash);
+        assert!(block.mine_in_range(1, 0, 1000));
+        assert!(block.verify(""));
+
+        let decoded = Block::<Blake2sHasher>::from_bytes(&block.to_bytes()).unwrap();
+        assert_eq!(decoded.miner_note(), b"/my-pool/");
+        assert_eq!(decoded, block);
+
+        let hash = block.hash();
+        assert_eq!(
+            block.set_miner_note(vec![0; MAX_MINER_NOTE_LEN + 1]),
+            Err("Miner note is too long: 65 > 64".to_string())
+        );
+        assert_eq!(block.miner_note(), b"/my-pool/");
+        assert_eq!(block.hash(), hash);
+        assert!(block.set_miner_note(vec![0; MAX_MINER_NOTE_LEN]).is_ok());
+    }
+
+    #[test]
+    fn test_from_bytes_never_panics() {
+        let mut rng = StdRng::seed_from_u64(351);
+        let keypair = Keypair::generate(&mut rng);
+        let mut block = Block::new(Some("prev".to_string()));
+        block.add_transaction(create_account_tx("alice".to_string()));
+        block.add_transaction(
+            TransactionBuilder::new(TransactionData::RotateKey {
+                new_public_key: keypair.public,
+            })
+            .from("alice".to_string())
+            .sign(&keypair, ""),
+        );
+        let bytes = block.to_bytes();
+
+        let decode = |input: &[u8]| {
+            if let Ok(block) = Block::<Blake2sHasher>::from_bytes(input) {
+                block.verify("");
+            }
+        };
+        // Truncated at every length
+        for len in 0..bytes.len() {
+            assert!(Block::<Blake2sHasher>::from_bytes(&bytes[..len]).is_err());
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Block::<Blake2sHasher>::from_bytes(&trailing).is_err());
+        // Single corrupted bytes, which also hit lengths and keys
+        for i in 0..bytes.len() {
+            let mut corrupted = bytes.clone();
+            corrupted[i] = rng.gen();
+            decode(&corrupted);
+        }
+        for len in 0..1000 {
+            let mut random = vec![0u8; len % 300];
+            rng.fill_bytes(&mut random);
+            decode(&random);
+        }
+        // Length prefix far beyond the input
+        assert!(Block::<Blake2sHasher>::from_bytes(&[0xff; 64]).is_err());
     }
 }
@@ -1,30 +1,276 @@
+use std::cmp::Reverse;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
 
 use ed25519_dalek::PublicKey;
+use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::traits::{Hashable, Verifiable, WorldState};
+use crate::traits::{Hashable, Hasher, Verifiable, WorldState, WorldStateRead};
 use crate::types::account::Account;
 use crate::types::chain::Chain;
-use crate::types::{AccountId, AccountType, Block, Error, Hash, Transaction};
+use crate::types::{
+    normalize_account_id, AccountId, AccountType, Balance, Blake2sHasher, Block, BlockHeader,
+    BlockReceipt, ChainEvent, ConsensusConfig, Error, GenesisPolicy, Hash, Journaled,
+    StateSnapshot, Timestamp, Transaction, TransactionData, TransactionReceipt, MAX_MINER_NOTE_LEN,
+};
+use crate::utils::{current_timestamp, leading_zero_bits, short_hex, HexKey};
 
-#[derive(Debug, Default, Clone)]
-pub struct Blockchain {
-    pub blocks: Chain<Block>,
+/// Default for the most transactions in one block
+pub const MAX_TXS_PER_BLOCK: usize = 100;
+/// Default for the largest accepted block in the `Block::to_bytes` encoding
+pub const MAX_BLOCK_BYTES: usize = 12 * 1024;
+/// Most orphan blocks kept by `submit_block`, the oldest is dropped first
+pub const MAX_ORPHANS: usize = 100;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(bound(serialize = "", deserialize = "H: Default"))]
+pub struct Blockchain<H = Blake2sHasher> {
+    #[serde(deserialize_with = "deserialize_blocks")]
+    pub blocks: Chain<Block<H>>,
+    pub accounts: HashMap<AccountId, Account>,
+    /// Ordered by fee, highest first, then by timestamp, oldest first
+    pub transactions_pool: Vec<Transaction<H>>,
+    #[serde(default)]
+    pub max_supply: Option<Balance>,
+    /// Account allowed to send `SetFrozen` transactions
+    #[serde(default)]
+    pub admin: Option<AccountId>,
+    /// Paid by the sender of a transaction creating an account, the funder
+    #[serde(default)]
+    pub account_creation_fee: Balance,
+    /// Only accept `CreateAccount` with ids made by `utils::derive_account_id`
+    #[serde(default)]
+    pub require_derived_account_ids: bool,
+    /// Most transactions the pool keeps, unlimited if not set
+    #[serde(default)]
+    pub max_pool_size: Option<usize>,
+    /// Checked against the genesis block when it's appended
+    #[serde(default)]
+    pub genesis_policy: GenesisPolicy,
+    #[serde(default)]
+    pub consensus: ConsensusConfig,
+    /// Transactions have to be signed for this network, signatures made for
+    /// another one are rejected. If not set, it's the genesis hash, so chains
+    /// with different genesis blocks never accept each other's signatures.
+    /// Genesis transactions are signed before that hash exists, for the unset id.
+    #[serde(default)]
+    pub network_id: String,
+    /// Hash of the appended genesis block, kept when the block is pruned
+    #[serde(default)]
+    genesis_hash: Option<Hash>,
+    /// Known block hashes by height, blocks below the latest one aren't validated
+    #[serde(default)]
+    checkpoints: BTreeMap<usize, Hash>,
+    /// Blocks dropped by `prune`, zero in files saved before pruning existed
+    #[serde(default)]
+    pruned: usize,
+    /// Received blocks whose parent isn't known yet, oldest first
+    #[serde(skip)]
+    orphans: Vec<Block<H>>,
+    #[serde(skip)]
+    subscribers: Vec<Sender<ChainEvent>>,
+    #[serde(skip)]
+    pending: Option<PendingPool<H>>,
+    /// Height of the kept block with each transaction, rebuilt on load
+    #[serde(skip)]
+    tx_heights: HashMap<Hash, usize>,
+    #[serde(skip)]
+    hasher: H,
+}
+
+/// Accounts as they are after the pooled transactions, so `add_to_pool` only
+/// executes the new one. Rebuilt when the head, the pool or the accounts
+/// changed some other way.
+#[derive(Debug, Clone)]
+struct PendingPool<H> {
+    head: Option<Hash>,
+    /// Hashes of the pooled transactions, in pool order
+    pool: Vec<Hash>,
+    accounts: HashMap<AccountId, Account>,
+    /// Pooled transactions that execute, in execution order, with the before-images
+    /// of the accounts each one wrote
+    executed: Vec<(Transaction<H>, StateSnapshot)>,
+}
+
+impl<H: Hasher> PendingPool<H> {
+    /// The account as the `k`th executed transaction left it: as the next one
+    /// writing it found it, or as it is now
+    fn after(&self, k: usize, account_id: &AccountId) -> Option<&Account> {
+        self.executed[k + 1..]
+            .iter()
+            .find_map(|(_, written)| {
+                written
+                    .entries()
+                    .find(|(id, _)| *id == account_id)
+                    .map(|(_, before)| before)
+            })
+            .unwrap_or_else(|| self.accounts.get(account_id))
+    }
+
+    /// Whether undoing the `k`th executed transaction could make a later one fail.
+    /// Accounts it created or changed beyond the balance must be left alone by
+    /// the later ones, accounts it credited must not be spent from afterwards.
+    fn is_depended_on(&self, k: usize) -> bool {
+        let later = &self.executed[k + 1..];
+        self.executed[k].1.entries().any(|(account_id, before)| {
+            let after = self.after(k, account_id);
+            match (before, after) {
+                (Some(before), Some(after)) if changes_only_balance(before, after) => {
+                    let mut balances = later
+                        .iter()
+                        .filter_map(|(_, written)| {
+                            written.entries().find(|(id, _)| *id == account_id)
+                        })
+                        .map(|(_, account)| account.map(|account| account.balance))
+                        .chain(Some(
+                            self.accounts.get(account_id).map(|account| account.balance),
+                        ))
+                        .collect::<Vec<_>>();
+                    balances.insert(0, Some(after.balance));
+                    after.balance > before.balance
+                        && balances.windows(2).any(|pair| pair[1] < pair[0])
+                }
+                _ => later.iter().any(|(tx, written)| {
+                    tx.involves(account_id) || written.entries().any(|(id, _)| id == account_id)
+                }),
+            }
+        })
+    }
+
+    /// Takes back what the `k`th executed transaction did, which nothing may
+    /// depend on. Balances keep what later transactions changed.
+    fn undo(&mut self, k: usize) -> Transaction<H> {
+        let changes = self.executed[k]
+            .1
+            .entries()
+            .map(|(id, before)| (id.clone(), before.cloned(), self.after(k, id).cloned()))
+            .collect::<Vec<_>>();
+        for (account_id, before, after) in changes {
+            match (before, after, self.accounts.get_mut(&account_id)) {
+                (Some(before), Some(after), Some(account)) => {
+                    let balance = account
+                        .balance
+                        .saturating_add(before.balance)
+                        .saturating_sub(after.balance);
+                    if !changes_only_balance(&before, &after) {
+                        *account = before;
+                    }
+                    account.balance = balance;
+                }
+                (Some(before), _, _) => {
+                    self.accounts.insert(account_id, before);
+                }
+                (None, _, _) => {
+                    self.accounts.remove(&account_id);
+                }
+            }
+        }
+        self.executed.remove(k).0
+    }
+}
+
+fn changes_only_balance(before: &Account, after: &Account) -> bool {
+    let mut unchanged = before.clone();
+    unchanged.balance = after.balance;
+    unchanged == *after
+}
+
+/// Chain files saved before blocks had a genesis marker load it as unset, a
+/// first block without `prev_hash` was genesis then
+fn deserialize_blocks<'de, D: Deserializer<'de>, H: Default>(
+    deserializer: D,
+) -> Result<Chain<Block<H>>, D::Error> {
+    let mut blocks = Chain::<Block<H>>::deserialize(deserializer)?;
+    if let Some(first) = blocks.iter_mut().last() {
+        first.is_genesis |= first.prev_hash.is_none();
+    }
+    Ok(blocks)
+}
+
+/// Outcome of a transaction executed against a copy of the state
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationResult {
+    pub balances: HashMap<AccountId, Balance>,
+    /// Burned transfer fee or account creation fee
+    pub fee: Balance,
+}
+
+/// World state without the blocks it was built from, see `Blockchain::export_state`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateExport {
     pub accounts: HashMap<AccountId, Account>,
-    pub transactions_pool: Vec<Transaction>,
+    /// `Blockchain::state_hash` of the exported accounts
+    pub state_hash: Hash,
+}
+
+/// Summary line followed by one line per block, oldest first
+impl<H: Hasher> fmt::Display for Blockchain<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Blockchain height {}, head {}, {} accounts, total supply {}",
+            self.height(),
+            self.get_last_block_hash()
+                .map_or("none".to_string(), |hash| short_hex(&hash)),
+            self.accounts.len(),
+            self.total_supply()
+        )?;
+        for (index, block) in self.blocks.iter_forward().enumerate() {
+            write!(f, "\n  #{} {}", self.pruned + index + 1, block)?;
+        }
+        Ok(())
+    }
 }
 
-impl WorldState for Blockchain {
+impl<H: Hasher> WorldStateRead for Blockchain<H> {
     fn get_account_ids(&self) -> Vec<AccountId> {
         self.accounts.keys().cloned().collect()
     }
 
+    /// Ids are stored normalized, others are looked up as they'd be created
     fn get_account_by_id(&self, id: &AccountId) -> Option<&Account> {
-        self.accounts.get(id)
+        self.accounts
+            .get(id)
+            .or_else(|| self.accounts.get(&normalize_account_id(id).ok()?))
+    }
+
+    fn network_id(&self) -> &str {
+        match &self.genesis_hash {
+            Some(genesis_hash) if self.network_id.is_empty() => genesis_hash,
+            _ => &self.network_id,
+        }
+    }
+
+    fn admin(&self) -> Option<&AccountId> {
+        self.admin.as_ref()
+    }
+
+    fn requires_derived_account_ids(&self) -> bool {
+        self.require_derived_account_ids
     }
 
+    fn min_transfer(&self) -> Balance {
+        self.consensus.min_transfer
+    }
+
+    fn account_creation_fee(&self) -> Balance {
+        self.account_creation_fee
+    }
+
+    fn max_supply(&self) -> Option<Balance> {
+        self.max_supply
+    }
+}
+
+impl<H: Hasher> WorldState for Blockchain<H> {
     fn get_account_by_id_mut(&mut self, id: &AccountId) -> Option<&mut Account> {
+        self.pending = None;
         self.accounts.get_mut(id)
     }
 
@@ -34,6 +280,7 @@ impl WorldState for Blockchain {
         account_type: AccountType,
         public_key: PublicKey,
     ) -> Result<(), Error> {
+        self.pending = None;
         match self.accounts.entry(account_id.clone()) {
             Entry::Occupied(_) => Err(format!("AccountId already exist: {}", account_id)),
             Entry::Vacant(v) => {
@@ -48,172 +295,3545 @@ impl Blockchain {
     pub fn new() -> Self {
         Default::default()
     }
+}
+
+impl<H: Hasher> Blockchain<H> {
+    pub fn with_hasher(hasher: H) -> Self {
+        Self {
+            hasher,
+            ..Default::default()
+        }
+    }
 
+    /// Number of blocks kept in memory, see `height` for the whole chain
     pub fn len(&self) -> usize {
         self.blocks.len()
     }
 
-    pub fn append_block(&mut self, block: Block) -> Result<(), Error> {
-        if !block.verify() {
+    /// Number of blocks since genesis, including pruned ones. Heights are 1-based
+    /// everywhere: genesis is at height 1 and the head at `height()`.
+    pub fn height(&self) -> usize {
+        self.pruned + self.blocks.len()
+    }
+
+    /// Whether the next appended block has to be genesis
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Executes the block's transactions and appends it, returning its hash
+    pub fn append_block(&mut self, block: Block<H>) -> Result<Hash, Error> {
+        self.append_block_with_receipt(block)
+            .map(|receipt| receipt.block_hash)
+    }
+
+    /// Like `append_block`, also returns the receipts of the block's transactions
+    pub fn append_block_with_receipt(&mut self, block: Block<H>) -> Result<BlockReceipt, Error> {
+        let hash = self.check_block_header(&block)?;
+        let transactions = Self::execute_block(self, &block, &hash)?;
+
+        log::debug!("Appended block {} at height {}", hash, self.height() + 1);
+        if block.is_genesis() {
+            self.genesis_hash = Some(hash.clone());
+        }
+        self.index_transactions(&block, self.height() + 1);
+        self.blocks.append(block);
+        self.emit(ChainEvent::BlockAppended(hash.clone()));
+        Ok(BlockReceipt {
+            block_hash: hash,
+            transactions,
+        })
+    }
+
+    /// Appends a block received from the network. A block whose parent isn't
+    /// known is kept as an orphan until the parent arrives. Returns how many
+    /// blocks were appended, the submitted one and orphans connected after it.
+    pub fn submit_block(&mut self, block: Block<H>) -> Result<usize, Error> {
+        let extends_head = block.is_genesis() || block.prev_hash == self.get_last_block_hash();
+        if !extends_head {
+            let parent_known = block.prev_hash.as_ref().is_some_and(|prev_hash| {
+                self.blocks
+                    .contains(|known| known.hash.as_ref() == Some(prev_hash))
+            });
+            if parent_known {
+                return Err("Block doesn't extend the head.".to_string());
+            }
+            if !self.orphans.contains(&block) {
+                if self.orphans.len() >= MAX_ORPHANS {
+                    self.orphans.remove(0);
+                }
+                log::debug!("Keeping orphan block {}", block.hash());
+                self.orphans.push(block);
+            }
+            return Ok(0);
+        }
+
+        self.append_block(block)?;
+        let mut appended = 1;
+        while let Some(i) = self
+            .orphans
+            .iter()
+            .position(|orphan| orphan.prev_hash == self.get_last_block_hash())
+        {
+            let orphan = self.orphans.remove(i);
+            match self.append_block(orphan) {
+                Ok(_) => appended += 1,
+                Err(error) => log::warn!("Dropping orphan block: {}", error),
+            }
+        }
+        Ok(appended)
+    }
+
+    pub fn orphans(&self) -> &[Block<H>] {
+        &self.orphans
+    }
+
+    /// Runs every check of `append_block` against a copy of the state, so a
+    /// received block can be vetted before it's committed
+    pub fn check_block(&self, block: &Block<H>) -> Result<(), Error> {
+        let hash = self.check_block_header(block)?;
+        let mut state = self.with_accounts(self.accounts.clone());
+        Self::execute_block(&mut state, block, &hash).map(|_| ())
+    }
+
+    /// State hash the chain would have with the block appended, for the block to
+    /// commit to
+    pub fn state_root_after(&self, block: &Block<H>) -> Result<Hash, Error> {
+        let mut state = self.with_accounts(self.accounts.clone());
+        for transaction in block.transactions.iter() {
+            transaction.execute(&mut state, block.is_genesis())?;
+        }
+        Ok(state.state_hash())
+    }
+
+    /// Checks that don't need to execute transactions, returns the block hash.
+    /// The hash is recomputed once and everything is checked against it, the
+    /// stored one only has to match.
+    fn check_block_header(&self, block: &Block<H>) -> Result<Hash, Error> {
+        let hash = block.hash();
+        if block.hash.as_ref() != Some(&hash) {
             return Err("Block has invalid hash".to_string());
         }
 
-        let is_genesis = self.blocks.len() == 0;
+        if leading_zero_bits(&hash) < self.consensus.difficulty {
+            return Err(format!(
+                "Block doesn't meet the difficulty of {} bits",
+                self.consensus.difficulty
+            ));
+        }
 
-        if !is_genesis && block.transactions.len() == 0 {
-            return Err("Block has 0 transaction.".to_string());
+        // Decoded blocks skip `set_miner_note`
+        if block.miner_note().len() > MAX_MINER_NOTE_LEN {
+            return Err(format!(
+                "Miner note is too long: {} > {}",
+                block.miner_note().len(),
+                MAX_MINER_NOTE_LEN
+            ));
         }
 
-        let account_backup = self.accounts.clone();
-        for transaction in block.transactions.clone() {
-            let result = transaction.execute(self, is_genesis);
-            if let Err(error) = result {
-                self.accounts = account_backup;
-                return Err(format!("Error during executing transactions: {}", error));
+        let size = block.size_bytes();
+        if size > self.consensus.max_block_bytes {
+            return Err(format!(
+                "Block is too large: {} > {} bytes",
+                size, self.consensus.max_block_bytes
+            ));
+        }
+        if let Some(max) = self.consensus.max_txs_per_account {
+            let mut counts = HashMap::new();
+            for from in block.transactions.iter().filter_map(|tx| tx.from()) {
+                let count = counts.entry(from).or_insert(0);
+                *count += 1;
+                if *count > max {
+                    return Err(format!(
+                        "Too many transactions from {}: {} > {}",
+                        from, count, max
+                    ));
+                }
             }
         }
+        if block.transactions.len() > self.consensus.max_txs_per_block {
+            return Err(format!(
+                "Block has too many transactions: {} > {}",
+                block.transactions.len(),
+                self.consensus.max_txs_per_block
+            ));
+        }
 
-        self.blocks.append(block);
-        Ok(())
-    }
+        let is_genesis = block.is_genesis();
+        // Checked apart from the genesis marker, which a crafted block may lack
+        if block.prev_hash.is_none() && !self.is_empty() {
+            return Err("Unexpected genesis: block without prev_hash.".to_string());
+        }
+        if is_genesis && !self.is_empty() {
+            return Err("Only the first block can be genesis.".to_string());
+        }
+        if !is_genesis && self.is_empty() {
+            return Err("First block must be genesis.".to_string());
+        }
+        if !is_genesis && block.prev_hash != self.get_last_block_hash() {
+            return Err("Block doesn't extend the head.".to_string());
+        }
+        if let Some(head) = self.blocks.head() {
+            if block.timestamp() <= head.timestamp() {
+                return Err(format!(
+                    "Block timestamp {} isn't after the head's {}",
+                    block.timestamp(),
+                    head.timestamp()
+                ));
+            }
+        }
+        if is_genesis {
+            self.genesis_policy.check(block)?;
+        }
 
-    pub fn get_last_block_hash(&self) -> Option<Hash> {
-        self.blocks.head().map(|last_block| last_block.hash())
+        if !is_genesis && block.transactions.is_empty() {
+            return Err("Block has 0 transaction.".to_string());
+        }
+        if !is_genesis && block.state_root().is_none() {
+            return Err("Block has no state root.".to_string());
+        }
+
+        if let Some(tx) = block
+            .transactions
+            .iter()
+            .find(|tx| tx.is_expired(block.timestamp()))
+        {
+            return Err(format!("Transaction expired: {}", tx.hash()));
+        }
+
+        Ok(hash)
     }
 
-    pub fn validate(&self) -> Result<(), Error> {
-        let mut block_num = self.blocks.len();
-        let mut prev_block_hash: Option<Hash> = None;
+    /// Executes the block's transactions on `state`, leaving it untouched if any fails
+    fn execute_block(
+        state: &mut Self,
+        block: &Block<H>,
+        hash: &Hash,
+    ) -> Result<Vec<TransactionReceipt>, Error> {
+        let is_genesis = block.is_genesis();
+        let mut journal = Journaled::new(&mut *state);
+        let mut receipts = Vec::with_capacity(block.transactions.len());
+        let mut failure = None;
+        for transaction in &block.transactions {
+            // A panicking transaction is rolled back like a failing one, so the
+            // block is never left half-applied
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                transaction.execute_with_receipt(&mut journal, is_genesis)
+            }))
+            .unwrap_or_else(|payload| Err(panic_message(payload.as_ref())));
+            match result {
+                Ok(receipt) => receipts.push(receipt),
+                Err(error) => {
+                    failure = Some(error);
+                    break;
+                }
+            }
+            log::debug!("Executed transaction {}", transaction.hash());
+        }
+        let snapshot = journal.into_snapshot();
+        if let Some(error) = failure {
+            log::warn!("Rolling back block {}: {}", hash, error);
+            snapshot.restore(&mut state.accounts);
+            return Err(format!("Error during executing transactions: {}", error));
+        }
 
-        for block in self.blocks.iter() {
-            let is_genesis = block_num == 1;
+        if let Some(state_root) = block.state_root() {
+            let computed = state.state_hash();
+            if &computed != state_root {
+                log::warn!("Rolling back block {}: state root mismatch", hash);
+                snapshot.restore(&mut state.accounts);
+                return Err(format!(
+                    "State root mismatch: {} != {}",
+                    state_root, computed
+                ));
+            }
+        }
+        Ok(receipts)
+    }
+
+    /// Adds a transaction to the pool if it can be executed on top of the current
+    /// state together with all transactions that are already pooled. A full pool
+    /// makes room by evicting its lowest-fee transaction that no other pooled one
+    /// depends on, which is returned. Only the new transaction is executed, on
+    /// the accounts as the pooled ones leave them.
+    pub fn add_to_pool(
+        &mut self,
+        transaction: Transaction<H>,
+    ) -> Result<Option<Transaction<H>>, Error> {
+        if transaction.is_expired(current_timestamp()) {
+            return Err(format!("Transaction expired: {}", transaction.hash()));
+        }
 
-            if !block.verify() {
-                return Err(format!("Block {} has invalid hash", block_num));
+        let mut pending = self.take_pending();
+        let mut state = self.with_accounts(std::mem::take(&mut pending.accounts));
+        let result = Self::execute_or_restore(&mut state, &transaction, self.is_empty());
+        pending.accounts = state.accounts;
+        match result {
+            Ok(written) => pending.executed.push((transaction.clone(), written)),
+            Err(error) => {
+                self.pending = Some(pending);
+                return Err(error);
             }
+        }
 
-            if block.prev_hash.is_none() && !is_genesis {
-                return Err(format!("Block {} doesn't have prev_hash", block_num));
+        let is_full = self
+            .max_pool_size
+            .is_some_and(|max_pool_size| self.transactions_pool.len() >= max_pool_size);
+        let mut evicted = None;
+        if is_full {
+            // Lowest fee first, only ones cheaper than the new transaction
+            let victim = (0..self.transactions_pool.len())
+                .rev()
+                .take_while(|&i| self.transactions_pool[i].fee() < transaction.fee())
+                .find(|&i| {
+                    let tx = &self.transactions_pool[i];
+                    // One that failed on top of the others has nothing built on it
+                    pending
+                        .executed
+                        .iter()
+                        .position(|(executed, _)| executed == tx)
+                        .is_none_or(|k| !pending.is_depended_on(k))
+                });
+            let i = match victim {
+                Some(i) => i,
+                None => {
+                    pending.undo(pending.executed.len() - 1);
+                    self.pending = Some(pending);
+                    return Err("Transaction pool is full.".to_string());
+                }
+            };
+            let tx = self.transactions_pool.remove(i);
+            pending.pool.remove(i);
+            if let Some(k) = pending
+                .executed
+                .iter()
+                .position(|(executed, _)| *executed == tx)
+            {
+                pending.undo(k);
             }
+            evicted = Some(tx);
+        }
 
-            if block.prev_hash.is_some() && is_genesis {
-                return Err("Genesis block shouldn't have prev_hash".to_string());
+        let priority = |tx: &Transaction<H>| (Reverse(tx.fee()), tx.timestamp());
+        let position = self
+            .transactions_pool
+            .partition_point(|tx| priority(tx) <= priority(&transaction));
+        let hash = transaction.hash();
+        pending.pool.insert(position, hash.clone());
+        self.transactions_pool.insert(position, transaction);
+        self.pending = Some(pending);
+        self.emit(ChainEvent::TransactionPooled(hash));
+        Ok(evicted)
+    }
+
+    /// The pending view of the current head and pool, rebuilt if it's outdated
+    fn take_pending(&mut self) -> PendingPool<H> {
+        let head = self.get_last_block_hash();
+        let pool = self
+            .transactions_pool
+            .iter()
+            .map(|tx| tx.hash())
+            .collect::<Vec<_>>();
+        match self.pending.take() {
+            Some(pending) if pending.head == head && pending.pool == pool => pending,
+            _ => {
+                let (state, selected) =
+                    self.select_transactions(&self.transactions_pool, usize::MAX, usize::MAX, None);
+                PendingPool {
+                    head,
+                    pool,
+                    accounts: state.accounts,
+                    executed: selected
+                        .into_iter()
+                        .map(|(i, written)| (self.transactions_pool[i].clone(), written))
+                        .collect(),
+                }
             }
+        }
+    }
 
-            if block_num != self.blocks.len() {
-                if let Some(prev_block_hash) = &prev_block_hash {
-                    if prev_block_hash != &block.hash.clone().unwrap() {
-                        return Err(format!(
-                            "Block {} prev_hash doesn't match Block {} hash",
-                            block_num + 1,
-                            block_num
-                        ));
+    /// Receives an event for every block appended and every transaction pooled
+    /// from now on, clones of the chain keep sending to it too. Dropped receivers
+    /// are forgotten on the next event.
+    pub fn subscribe(&mut self) -> Receiver<ChainEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    fn emit(&mut self, event: ChainEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    /// Drops pooled transactions that a block made at `now` can't include
+    pub fn evict_expired(&mut self, now: Timestamp) {
+        self.transactions_pool.retain(|tx| !tx.is_expired(now));
+    }
+
+    /// Picks up to `max_count` transactions of at most `max_bytes` in total that can
+    /// be executed on top of the current state, trying them in pool order. Skipped ones are retried while that makes
+    /// progress, as they may depend on a transaction with a lower fee. Each sender
+    /// gets at most `max_per_account` of them. Returns the resulting state and
+    /// positions of the picked transactions in execution order, each with the
+    /// before-images of the accounts it wrote.
+    fn select_transactions(
+        &self,
+        pool: &[Transaction<H>],
+        max_count: usize,
+        max_bytes: usize,
+        max_per_account: Option<usize>,
+    ) -> (Self, Vec<(usize, StateSnapshot)>) {
+        let is_genesis = self.is_empty();
+        let mut state = self.with_accounts(self.accounts.clone());
+        let mut selected: Vec<(usize, StateSnapshot)> = Vec::new();
+        let mut bytes = 0;
+
+        loop {
+            let selected_before = selected.len();
+            for (i, tx) in pool.iter().enumerate() {
+                if selected.len() == max_count || selected.iter().any(|(j, _)| *j == i) {
+                    continue;
+                }
+                let size = tx.size_bytes();
+                if bytes + size > max_bytes {
+                    continue;
+                }
+                if let (Some(from), Some(max)) = (tx.from(), max_per_account) {
+                    let sent = selected
+                        .iter()
+                        .filter(|&&(j, _)| pool[j].from() == Some(from))
+                        .count();
+                    if sent >= max {
+                        continue;
                     }
                 }
+
+                if let Ok(written) = Self::execute_or_restore(&mut state, tx, is_genesis) {
+                    selected.push((i, written));
+                    bytes += size;
+                }
             }
 
-            prev_block_hash = block.prev_hash.clone();
-            block_num -= 1;
+            if selected.len() == selected_before {
+                return (state, selected);
+            }
+        }
+    }
+
+    /// Digest of all block hashes folded oldest first with the chain's hasher.
+    /// Chains with the same blocks in the same order have the same fingerprint.
+    pub fn fingerprint(&self) -> Hash {
+        self.blocks
+            .fold_chronological(String::new(), |fingerprint, block| {
+                self.hasher
+                    .digest(format!("{}{}", fingerprint, block.hash()).as_bytes())
+            })
+    }
+
+    /// Hash of all accounts ordered by id, equal on nodes with the same state
+    pub fn state_hash(&self) -> Hash {
+        let mut account_ids = self.accounts.keys().collect::<Vec<_>>();
+        account_ids.sort();
+        let mut data = Vec::new();
+        for id in account_ids {
+            let account = &self.accounts[id];
+            let metadata = account.metadata.iter().collect::<BTreeMap<_, _>>();
+            data.extend(
+                format!(
+                    "{:?}",
+                    (
+                        id,
+                        &account.account_type,
+                        account.balance,
+                        HexKey(&account.public_key),
+                        account.frozen,
+                        account.minted,
+                        metadata
+                    )
+                )
+                .into_bytes(),
+            );
+        }
+        self.hasher.digest(&data)
+    }
+
+    pub fn export_state(&self) -> StateExport {
+        StateExport {
+            accounts: self.accounts.clone(),
+            state_hash: self.state_hash(),
         }
+    }
 
+    /// Replaces all accounts with the exported ones, blocks and the pool are kept.
+    /// Fails without changes if the accounts don't match the export's hash.
+    pub fn import_state(&mut self, export: StateExport) -> Result<(), Error> {
+        let state = self.with_accounts(export.accounts);
+        let state_hash = state.state_hash();
+        if state_hash != export.state_hash {
+            return Err(format!(
+                "State hash mismatch: {} != {}",
+                state_hash, export.state_hash
+            ));
+        }
+        self.accounts = state.accounts;
+        self.pending = None;
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::types::TransactionData;
-    use crate::utils::{create_account_tx, generate_random_account};
-    use ed25519_dalek::{Keypair, Signer};
+    /// Executes the transaction on a copy of the state, leaving the chain
+    /// untouched, and returns the resulting balances of the accounts it wrote to.
+    pub fn simulate(&self, tx: &Transaction<H>) -> Result<SimulationResult, Error> {
+        let mut state = self.with_accounts(self.accounts.clone());
+        let receipt = tx.execute_with_receipt(&mut state, self.is_empty())?;
 
-    use super::*;
+        Ok(SimulationResult {
+            balances: receipt
+                .changes
+                .into_iter()
+                .filter(|change| state.accounts.contains_key(&change.account_id))
+                .map(|change| (change.account_id, change.after))
+                .collect(),
+            fee: receipt.fee,
+        })
+    }
 
-    fn append_block(bc: &mut Blockchain, nonce: u128) -> Block {
-        let mut block = Block::new(bc.get_last_block_hash());
-        block.set_nonce(nonce);
-        block.add_transaction(create_account_tx(generate_random_account()));
-        let block_clone = block.clone();
-        assert!(bc.append_block(block).is_ok());
-        block_clone
+    /// Outcome of each transaction executed in order against a copy of the state,
+    /// as if they were the next block. Failed ones are rolled back and leave no
+    /// effect on the ones after them.
+    pub fn validate_transaction_set(&self, txs: &[Transaction<H>]) -> Vec<Result<(), Error>> {
+        let mut state = self.with_accounts(self.accounts.clone());
+        let is_genesis = self.is_empty();
+        txs.iter()
+            .map(|tx| Self::execute_or_restore(&mut state, tx, is_genesis).map(|_| ()))
+            .collect()
     }
 
-    fn append_block_with_tx(
-        bc: &mut Blockchain,
-        nonce: u128,
-        transactions: Vec<Transaction>,
-    ) -> Result<Block, Error> {
-        let mut block = Block::new(bc.get_last_block_hash());
-        block.set_nonce(nonce);
-        for transaction in transactions {
-            block.add_transaction(transaction);
+    /// Executes the transaction on `state`, undoing whatever it wrote if it fails.
+    /// Returns the before-images of the accounts it wrote.
+    fn execute_or_restore(
+        state: &mut Self,
+        tx: &Transaction<H>,
+        is_genesis: bool,
+    ) -> Result<StateSnapshot, Error> {
+        let mut journal = Journaled::new(&mut *state);
+        let result = tx.execute(&mut journal, is_genesis);
+        let snapshot = journal.into_snapshot();
+        match result {
+            Ok(()) => Ok(snapshot),
+            Err(error) => {
+                snapshot.restore(&mut state.accounts);
+                Err(error)
+            }
         }
-        let block_clone = block.clone();
-        bc.append_block(block)?;
+    }
 
-        Ok(block_clone)
+    pub fn balance_of(&self, id: &AccountId) -> Option<Balance> {
+        self.get_account_by_id(id).map(|account| account.balance)
     }
 
-    #[test]
-    fn test_last_block_none() {
-        assert_eq!(Blockchain::new().get_last_block_hash(), None);
+    /// Up to `limit` accounts with the highest balances, ties ordered by id
+    pub fn accounts_by_balance(&self, limit: usize) -> Vec<(AccountId, Balance)> {
+        let mut accounts = self
+            .get_account_ids()
+            .into_iter()
+            .filter_map(|id| {
+                let balance = self.balance_of(&id)?;
+                Some((id, balance))
+            })
+            .collect::<Vec<_>>();
+        accounts.sort_by(|(a_id, a_balance), (b_id, b_balance)| {
+            b_balance.cmp(a_balance).then_with(|| a_id.cmp(b_id))
+        });
+        accounts.truncate(limit);
+        accounts
     }
 
-    #[test]
-    fn test_last_block() {
-        let mut bc = Blockchain::new();
+    /// Whether the confirmed balance covers the amount and the fee. Unknown
+    /// accounts can't afford anything.
+    pub fn can_afford(&self, id: &AccountId, amount: Balance, fee: Balance) -> bool {
+        match (self.balance_of(id), amount.checked_add(fee)) {
+            (Some(balance), Ok(total)) => balance >= total,
+            _ => false,
+        }
+    }
 
-        append_block(&mut bc, 1);
-        append_block(&mut bc, 2);
-        let last_block = append_block(&mut bc, 3);
-        let last_block_hash = last_block.hash();
+    /// Confirmed balance with the pooled transactions applied in pool order, as a
+    /// wallet would show it. Overdrafts clamp at 0.
+    pub fn pending_balance_of(&self, id: &AccountId) -> Balance {
+        let confirmed = self
+            .accounts
+            .get(id)
+            .map_or(Balance::ZERO, |account| account.balance);
+        self.transactions_pool
+            .iter()
+            .fold(confirmed, |balance, tx| {
+                let sent = tx.from() == Some(id);
+                match &tx.data {
+                    TransactionData::Transfer { to, amount, fee } => {
+                        let balance = if sent {
+                            balance.saturating_sub(amount.saturating_add(*fee))
+                        } else {
+                            balance
+                        };
+                        if to == id {
+                            balance.saturating_add(*amount)
+                        } else {
+                            balance
+                        }
+                    }
+                    TransactionData::MintInitialSupply { to, amount } if to == id => {
+                        balance.saturating_add(*amount)
+                    }
+                    TransactionData::CreateAccount(..)
+                    | TransactionData::CreateMultisigAccount { .. }
+                        if sent && !self.is_empty() =>
+                    {
+                        balance.saturating_sub(self.account_creation_fee)
+                    }
+                    _ => balance,
+                }
+            })
+    }
 
-        assert_eq!(bc.len(), 3);
-        assert_eq!(bc.get_last_block_hash(), Some(last_block_hash));
+    /// Appends a new block on top of the head with as many pooled transactions
+    /// as the consensus rules allow, highest fees first. Mined transactions are
+    /// removed from the pool only if the block is accepted.
+    pub fn mine_pending_transactions(&mut self) -> Result<(), Error> {
+        // Blocks mined within the same millisecond still have to go forward in time
+        let timestamp = self
+            .blocks
+            .head()
+            .map_or(0, |head| head.timestamp() + 1)
+            .max(current_timestamp());
+        self.evict_expired(timestamp);
+
+        let mut block = Block::with_hasher(self.get_last_block_hash(), self.hasher.clone());
+        block.set_timestamp(timestamp);
+        block.set_nonce(0);
+        // Holds room for the real root, all state hashes have the same length
+        block.set_state_root(self.state_hash());
+        let (_, selected) = self.select_transactions(
+            &self.transactions_pool,
+            self.consensus.max_txs_per_block,
+            self.consensus
+                .max_block_bytes
+                .saturating_sub(block.size_bytes()),
+            self.consensus.max_txs_per_account,
+        );
+        for (i, _) in selected.iter() {
+            block.add_transaction(self.transactions_pool[*i].clone());
+        }
+        block.set_state_root(self.state_root_after(&block)?);
+        block.mine_in_range(self.consensus.difficulty, 0, u128::MAX);
+
+        self.append_block(block)?;
+        let mut i = 0;
+        self.transactions_pool.retain(|_| {
+            i += 1;
+            !selected.iter().any(|(j, _)| *j == i - 1)
+        });
+        Ok(())
     }
 
-    #[test]
-    fn test_validate() {
-        let bc = &mut Blockchain::new();
+    pub fn total_work(&self) -> u128 {
+        self.blocks
+            .fold_chronological(0, |work, block| work.saturating_add(block.work()))
+    }
 
-        append_block(bc, 1);
-        append_block(bc, 2);
-        append_block(bc, 3);
-        bc.blocks.iter_mut().next().unwrap().transactions[0] =
-            create_account_tx("malicios user".to_string());
+    /// Height and hash of the last block this chain shares with `other`, whose
+    /// blocks start at genesis. `None` when not even genesis is shared, or when
+    /// the chains diverge among pruned blocks.
+    pub fn common_ancestor(&self, other: &[Block<H>]) -> Option<(usize, Hash)> {
+        let shared = self
+            .blocks
+            .iter_forward()
+            .zip(other.iter().skip(self.pruned))
+            .take_while(|(ours, theirs)| ours.hash.as_ref() == Some(&theirs.hash()))
+            .count();
+        let height = self.pruned + shared;
+        self.blocks
+            .iter_forward()
+            .nth(shared.checked_sub(1)?)
+            .map(|block| (height, block.hash()))
+    }
 
-        assert_eq!(bc.validate(), Err(String::from("Block 3 has invalid hash")));
+    /// Replaces the chain with `other` if it has more total work and the same
+    /// genesis block. Only blocks of `other` after the common ancestor are
+    /// appended, so its accounts aren't trusted. Our own blocks up to the ancestor
+    /// are kept, on a fork their transactions are replayed to get the state the
+    /// new blocks start from. Pooled transactions that are still valid on the new
+    /// chain are kept.
+    pub fn try_replace_chain(&mut self, other: Self) -> Result<(), Error> {
+        if self.chain_id().is_some() && other.chain_id() != self.chain_id() {
+            return Err("Chain has a different genesis.".to_string());
+        }
+        if other.total_work() <= self.total_work() {
+            return Err("Chain doesn't have more work.".to_string());
+        }
+        if other.pruned > 0 {
+            return Err("Can't replace with a pruned chain.".to_string());
+        }
+        let blocks = other.blocks.into_vec();
+        let ancestor = self.common_ancestor(&blocks).map(|(height, _)| height);
+        if let Some(max_reorg_depth) = self.consensus.max_reorg_depth {
+            let depth = self.height() - ancestor.unwrap_or(0);
+            if depth > max_reorg_depth {
+                return Err(format!(
+                    "Reorg is too deep: {} > {} blocks",
+                    depth, max_reorg_depth
+                ));
+            }
+        }
+
+        let mut chain = match ancestor {
+            Some(height) if height == self.height() => {
+                let mut chain = self.with_accounts(self.accounts.clone());
+                chain.blocks = self.blocks.clone();
+                chain.pruned = self.pruned;
+                chain.tx_heights = self.tx_heights.clone();
+                chain
+            }
+            Some(height) if self.pruned == 0 => {
+                let mut chain = self.replay_until(height)?;
+                for block in self.blocks.iter_forward().take(height) {
+                    chain.index_transactions(block, chain.height() + 1);
+                    chain.blocks.append(block.clone());
+                }
+                chain
+            }
+            _ => self.without_blocks(),
+        };
+        chain.checkpoints = self.checkpoints.clone();
+        let mut appended = Vec::new();
+        for block in blocks.into_iter().skip(chain.height()) {
+            appended.push(chain.append_block(block)?);
+        }
+
+        for transaction in std::mem::take(&mut self.transactions_pool) {
+            let _ = chain.add_to_pool(transaction);
+        }
+        chain.subscribers = std::mem::take(&mut self.subscribers);
+        *self = chain;
+        for hash in appended {
+            self.emit(ChainEvent::BlockAppended(hash));
+        }
+        Ok(())
     }
 
-    #[test]
-    fn test_validate_prev_hash() {
-        let bc = &mut Blockchain::new();
+    /// Recomputes accounts by executing all blocks from genesis. Accounts are left
+    /// untouched if any block fails.
+    pub fn replay_state(&mut self) -> Result<(), Error> {
+        self.accounts = self.replay_until(self.len())?.accounts;
+        self.pending = None;
+        Ok(())
+    }
 
-        append_block(bc, 1);
-        append_block(bc, 2);
-        let block = bc.blocks.iter_mut().next().unwrap();
-        block.prev_hash = Some("invalid_prev_hash".to_string());
-        block.hash = Some(block.hash());
+    /// Balance of the account right after the block at `height`, counted from genesis
+    pub fn balance_at(&self, id: &AccountId, height: usize) -> Result<Balance, Error> {
+        if height == 0 || height > self.len() {
+            return Err(format!("Height {} is out of range", height));
+        }
+
+        self.replay_until(height)?
+            .accounts
+            .get(id)
+            .map(|account| account.balance)
+            .ok_or_else(|| format!("Account not found: {}", id))
+    }
+
+    /// Executes blocks from genesis up to and including `height` on an empty state
+    fn replay_until(&self, height: usize) -> Result<Self, Error> {
+        if self.pruned > 0 {
+            return Err("Can't replay a pruned chain.".to_string());
+        }
+
+        let (state, _) = self.blocks.fold_chronological(
+            Ok((self.without_blocks(), 1)),
+            |result: Result<(Self, usize), Error>, block| {
+                let (mut state, block_num) = result?;
+                if block_num > height {
+                    return Ok((state, block_num));
+                }
+                for transaction in block.transactions.iter() {
+                    transaction
+                        .execute(&mut state, block.is_genesis())
+                        .map_err(|error| {
+                            format!("Error replaying block {}: {}", block_num, error)
+                        })?;
+                }
+                if block.is_genesis() {
+                    state.genesis_hash = Some(block.hash());
+                }
+                if block
+                    .state_root()
+                    .is_some_and(|state_root| state_root != &state.state_hash())
+                {
+                    return Err(format!(
+                        "Error replaying block {}: state root doesn't match the state",
+                        block_num
+                    ));
+                }
+                Ok((state, block_num + 1))
+            },
+        )?;
+        Ok(state)
+    }
+
+    /// Removes all blocks except the `keep_last` most recent ones. Accounts state
+    /// is left untouched, the head block is always kept to link new blocks to.
+    pub fn prune(&mut self, keep_last: usize) {
+        while self.blocks.len() > keep_last.max(1) {
+            if let Some(block) = self.blocks.pop_tail() {
+                for transaction in block.transactions.iter() {
+                    let hash = transaction.hash();
+                    if self.tx_heights.get(&hash) == Some(&(self.pruned + 1)) {
+                        self.tx_heights.remove(&hash);
+                    }
+                }
+            }
+            self.pruned += 1;
+        }
+    }
+
+    /// Records the transactions of the block about to be kept at `height`. A
+    /// transaction repeated in a later block is found there.
+    fn index_transactions(&mut self, block: &Block<H>, height: usize) {
+        for transaction in block.transactions.iter() {
+            self.tx_heights.insert(transaction.hash(), height);
+        }
+    }
+
+    /// Iterates over transactions of all blocks, oldest first
+    pub fn iter_transactions(&self) -> impl Iterator<Item = &Transaction<H>> {
+        self.blocks
+            .iter_forward()
+            .flat_map(|block| block.transactions.iter())
+    }
+
+    /// Transfers of all blocks as `(from, to, amount)`, oldest first
+    pub fn transfers(&self) -> impl Iterator<Item = (&AccountId, &AccountId, Balance)> {
+        self.iter_transactions()
+            .filter_map(|tx| match (&tx.data, &tx.from) {
+                (TransactionData::Transfer { to, amount, .. }, Some(from)) => {
+                    Some((from, to, *amount))
+                }
+                _ => None,
+            })
+    }
+
+    /// Initial supply mints as `(to, amount)`
+    pub fn mints(&self) -> impl Iterator<Item = (&AccountId, Balance)> {
+        self.iter_transactions().filter_map(|tx| match &tx.data {
+            TransactionData::MintInitialSupply { to, amount } => Some((to, *amount)),
+            _ => None,
+        })
+    }
+
+    /// Created accounts with the keys they were created with
+    pub fn created_accounts(&self) -> impl Iterator<Item = (&AccountId, &PublicKey)> {
+        self.iter_transactions().filter_map(|tx| match &tx.data {
+            TransactionData::CreateAccount(account_id, public_key) => {
+                Some((account_id, public_key))
+            }
+            _ => None,
+        })
+    }
+
+    /// Transactions sent by or affecting the account, oldest first
+    pub fn transactions_for_account(&self, id: &AccountId) -> Vec<&Transaction<H>> {
+        self.iter_transactions()
+            .filter(|tx| tx.involves(id))
+            .collect()
+    }
+
+    /// The only block or transaction hash starting with `prefix`
+    pub fn resolve_short_hash(&self, prefix: &str) -> Result<Hash, Error> {
+        let prefix = prefix.to_lowercase();
+        let block_hashes = self.blocks.iter().map(|block| block.hash());
+        let tx_hashes = self.iter_transactions().map(|tx| tx.hash());
+        let mut matches = block_hashes
+            .chain(tx_hashes)
+            .filter(|hash| hash.starts_with(&prefix));
+
+        match (matches.next(), matches.next()) {
+            (Some(hash), None) => Ok(hash),
+            (Some(_), Some(_)) => Err(format!("Ambiguous hash prefix: {}", prefix)),
+            (None, _) => Err(format!("Hash not found: {}", prefix)),
+        }
+    }
+
+    /// Headers of the blocks from `from_height` on, counted from genesis, oldest
+    /// first. Pruned blocks are skipped.
+    pub fn headers(&self, from_height: usize) -> Vec<BlockHeader> {
+        self.blocks
+            .iter_forward()
+            .skip(from_height.saturating_sub(self.pruned + 1))
+            .map(Block::header)
+            .collect()
+    }
+
+    /// Up to `n` blocks from the head, newest first
+    pub fn recent_blocks(&self, n: usize) -> Vec<&Block<H>> {
+        self.blocks.iter().take(n).collect()
+    }
+
+    pub fn get_block_by_hash(&self, hash: &Hash) -> Option<&Block<H>> {
+        self.blocks.find(|block| block.hash.as_ref() == Some(hash))
+    }
+
+    /// Number of blocks built on top of the block with the transaction, 0 if it's
+    /// in the head block and None if it isn't in the chain
+    pub fn confirmations(&self, tx_hash: &Hash) -> Option<usize> {
+        self.tx_heights
+            .get(tx_hash)
+            .map(|height| self.height() - height)
+    }
+
+    /// Mean time between consecutive blocks in milliseconds. A genesis block
+    /// with the default zero timestamp isn't counted, it wasn't mined at a real time.
+    pub fn average_block_time(&self) -> Option<f64> {
+        let skip = self
+            .blocks
+            .tail()
+            .is_some_and(|tail| tail.is_genesis() && tail.timestamp() == 0);
+        let timestamps = self
+            .blocks
+            .iter_forward()
+            .skip(skip as usize)
+            .map(|block| block.timestamp())
+            .collect::<Vec<_>>();
+        if timestamps.len() < 2 {
+            return None;
+        }
+
+        let total = timestamps[timestamps.len() - 1].saturating_sub(timestamps[0]);
+        Some(total as f64 / (timestamps.len() - 1) as f64)
+    }
+
+    /// Graphviz diagram of the blocks, each pointing to its `prev_hash`
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph blockchain {\n    rankdir=RL;\n");
+        for block in self.blocks.iter() {
+            let hash = block.hash();
+            dot.push_str(&format!(
+                "    \"{}\" [label=\"{}\\n{} txs\"];\n",
+                hash,
+                &hash[..8.min(hash.len())],
+                block.transactions.len()
+            ));
+            if let Some(prev_hash) = &block.prev_hash {
+                dot.push_str(&format!("    \"{}\" -> \"{}\";\n", hash, prev_hash));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Empty chain with the same settings and the given accounts, used to
+    /// execute transactions without touching this chain
+    fn with_accounts(&self, accounts: HashMap<AccountId, Account>) -> Self {
+        Self {
+            accounts,
+            max_supply: self.max_supply,
+            admin: self.admin.clone(),
+            account_creation_fee: self.account_creation_fee,
+            require_derived_account_ids: self.require_derived_account_ids,
+            genesis_policy: self.genesis_policy.clone(),
+            consensus: self.consensus.clone(),
+            network_id: self.network_id.clone(),
+            genesis_hash: self.genesis_hash.clone(),
+            max_pool_size: self.max_pool_size,
+            hasher: self.hasher.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Empty state with the same configuration, to execute blocks from genesis
+    fn without_blocks(&self) -> Self {
+        let mut state = self.with_accounts(HashMap::new());
+        state.genesis_hash = None;
+        state
+    }
+
+    /// Hash of the genesis block, chains with different ids never share blocks.
+    /// Pruned chains don't have it.
+    pub fn chain_id(&self) -> Option<Hash> {
+        self.blocks
+            .tail()
+            .filter(|block| block.is_genesis())
+            .map(|block| block.hash())
+    }
+
+    /// Writes the blocks oldest first, each as `Block::to_bytes` prefixed with its
+    /// length as a big-endian `u32`. This is the stream `import_blocks` reads.
+    pub fn export_blocks<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        for block in self.blocks.iter_forward() {
+            let bytes = block.to_bytes();
+            writer
+                .write_all(&(bytes.len() as u32).to_be_bytes())
+                .and_then(|_| writer.write_all(&bytes))
+                .map_err(|error| error.to_string())?;
+        }
+        writer.flush().map_err(|error| error.to_string())
+    }
+
+    /// Builds a chain from an `export_blocks` stream. Blocks are read and appended
+    /// one at a time, so the stream is never held in memory, and the first bad
+    /// block fails the import with its height in the error.
+    pub fn import_blocks<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let mut chain = Self::default();
+        loop {
+            let height = chain.height() + 1;
+            let mut len = [0u8; 4];
+            match reader.read(&mut len[..1]) {
+                Ok(0) => return Ok(chain),
+                Ok(_) => reader.read_exact(&mut len[1..]),
+                Err(error) => Err(error),
+            }
+            .map_err(|error| format!("Block {}: {}", height, error))?;
+
+            let len = u32::from_be_bytes(len) as usize;
+            if len > chain.consensus.max_block_bytes {
+                return Err(format!(
+                    "Block {} is too large: {} > {} bytes",
+                    height, len, chain.consensus.max_block_bytes
+                ));
+            }
+            let mut bytes = vec![0u8; len];
+            reader
+                .read_exact(&mut bytes)
+                .map_err(|error| format!("Block {}: {}", height, error))?;
+            Block::from_bytes(&bytes)
+                .and_then(|block| chain.append_block(block))
+                .map_err(|error| format!("Block {}: {}", height, error))?;
+        }
+    }
+
+    /// Reads a chain saved as JSON by `save_to_file`
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let json = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let mut chain: Self = serde_json::from_str(&json).map_err(|error| error.to_string())?;
+        let pruned = chain.pruned;
+        chain.tx_heights = chain
+            .blocks
+            .iter_forward()
+            .enumerate()
+            .flat_map(|(index, block)| {
+                let height = pruned + index + 1;
+                block
+                    .transactions
+                    .iter()
+                    .map(move |transaction| (transaction.hash(), height))
+            })
+            .collect();
+        Ok(chain)
+    }
+
+    /// Like `load_from_file`, but fails unless the chain starts with the expected
+    /// genesis, so a node doesn't pick up a chain of another network
+    pub fn load_from_file_expecting_genesis<P: AsRef<Path>>(
+        path: P,
+        expected_genesis: Hash,
+    ) -> Result<Self, Error> {
+        let chain = Self::load_from_file(path)?;
+        match chain.chain_id() {
+            Some(chain_id) if chain_id == expected_genesis => Ok(chain),
+            chain_id => Err(format!(
+                "Genesis mismatch: expected {}, found {}",
+                expected_genesis,
+                chain_id.unwrap_or_else(|| "none".to_string())
+            )),
+        }
+    }
+
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let json = serde_json::to_string(self).map_err(|error| error.to_string())?;
+        fs::write(path, json).map_err(|error| error.to_string())
+    }
+
+    pub fn get_last_block_hash(&self) -> Option<Hash> {
+        self.blocks.head().map(|last_block| last_block.hash())
+    }
+
+    /// Marks the block at `height`, counted from genesis, as known to have `hash`.
+    /// Validation trusts blocks below the latest checkpoint.
+    pub fn set_checkpoint(&mut self, height: usize, hash: Hash) {
+        self.checkpoints.insert(height, hash);
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        match self.validate_all().into_iter().next() {
+            Some((_, error)) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Every problem of the chain with the height, counted from genesis, of the
+    /// block it was found in, head first. `validate` stops at the first of them.
+    pub fn validate_all(&self) -> Vec<(usize, Error)> {
+        let mut errors = Vec::new();
+        let mut height = self.height();
+        let mut prev_block_hash: Option<Hash> = None;
+        let mut next_timestamp: Option<Timestamp> = None;
+        // Latest checkpoint among blocks that weren't pruned
+        let checkpoint = self
+            .checkpoints
+            .range(self.pruned + 1..=self.height())
+            .next_back();
+        let mut replay_errors = self.replay_errors();
+
+        for block in self.blocks.iter() {
+            let is_genesis = block.is_genesis();
+            let is_first = height == 1;
+            let is_head = height == self.height();
+            let is_checkpoint = checkpoint.is_some_and(|(checkpoint, _)| *checkpoint == height);
+
+            if is_checkpoint && block.hash.as_ref() != checkpoint.map(|(_, hash)| hash) {
+                errors.push((height, format!("Block {} doesn't match checkpoint", height)));
+            }
+
+            if !block.verify(self.network_id()) {
+                errors.push((height, format!("Block {} has invalid hash", height)));
+            }
+
+            if is_genesis && !is_first {
+                errors.push((height, format!("Block {} shouldn't be genesis", height)));
+            }
+
+            if !is_genesis && is_first {
+                errors.push((height, "First block isn't genesis".to_string()));
+            }
+
+            if block.prev_hash.is_none() && !is_genesis {
+                errors.push((height, format!("Block {} doesn't have prev_hash", height)));
+            }
+
+            if block.prev_hash.is_some() && is_genesis {
+                errors.push((height, "Genesis block shouldn't have prev_hash".to_string()));
+            }
+
+            if !is_head {
+                if let Some(prev_block_hash) = &prev_block_hash {
+                    if Some(prev_block_hash) != block.hash.as_ref() {
+                        errors.push((
+                            height + 1,
+                            format!(
+                                "Block {} prev_hash doesn't match Block {} hash",
+                                height + 1,
+                                height
+                            ),
+                        ));
+                    }
+                }
+            }
+
+            if let Some(error) = replay_errors.remove(&height) {
+                errors.push((height, error));
+            } else if is_head
+                && block
+                    .state_root()
+                    .is_some_and(|state_root| state_root != &self.state_hash())
+            {
+                errors.push((
+                    height,
+                    format!("Block {} state root doesn't match the state", height),
+                ));
+            }
+
+            if next_timestamp.is_some_and(|timestamp| timestamp <= block.timestamp()) {
+                errors.push((
+                    height + 1,
+                    format!("Non-monotonic timestamp at block {}", height + 1),
+                ));
+            }
+
+            if is_checkpoint {
+                break;
+            }
+
+            prev_block_hash = block.prev_hash.clone();
+            next_timestamp = Some(block.timestamp());
+            height -= 1;
+        }
+
+        errors
+    }
+
+    /// Problems found executing the blocks from genesis, by height: state roots
+    /// that don't match and the first block that fails. Pruned chains can't be
+    /// replayed, only their head is checked against the current state.
+    fn replay_errors(&self) -> HashMap<usize, Error> {
+        let mut errors = HashMap::new();
+        if self.pruned > 0 {
+            return errors;
+        }
+
+        let mut state = self.without_blocks();
+        for (height, block) in (1..).zip(self.blocks.iter_forward()) {
+            for transaction in block.transactions.iter() {
+                if let Err(error) = transaction.execute(&mut state, block.is_genesis()) {
+                    errors.insert(
+                        height,
+                        format!("Block {} can't be replayed: {}", height, error),
+                    );
+                    return errors;
+                }
+            }
+            if block.is_genesis() {
+                state.genesis_hash = Some(block.hash());
+            }
+            if block
+                .state_root()
+                .is_some_and(|state_root| state_root != &state.state_hash())
+            {
+                errors.insert(
+                    height,
+                    format!("Block {} state root doesn't match the state", height),
+                );
+            }
+        }
+        errors
+    }
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> Error {
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause");
+    format!("Transaction panicked: {}", message)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{BalanceChange, Sha256Hasher, TransactionBuilder, MISSING_SENDER};
+    use crate::utils::{bootstrap_chain, create_account_tx, generate_random_account, sign_hash};
+    use ed25519_dalek::Keypair;
+
+    use super::*;
+
+    /// Commits a block to the state it leads to, blocks that fail keep any root
+    fn set_state_root(bc: &Blockchain, block: &mut Block) {
+        if !block.is_genesis() {
+            let state_root = bc
+                .state_root_after(block)
+                .unwrap_or_else(|_| bc.state_hash());
+            block.set_state_root(state_root);
+        }
+    }
+
+    fn append_block(bc: &mut Blockchain, nonce: u128) -> Block {
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.set_timestamp(bc.blocks.head().map_or(0, |head| head.timestamp() + 1));
+        block.set_nonce(nonce);
+        block.add_transaction(create_account_tx(generate_random_account()));
+        set_state_root(bc, &mut block);
+        let block_clone = block.clone();
+        assert!(bc.append_block(block).is_ok());
+        block_clone
+    }
+
+    fn append_block_with_tx(
+        bc: &mut Blockchain,
+        nonce: u128,
+        transactions: Vec<Transaction>,
+    ) -> Result<Block, Error> {
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.set_timestamp(bc.blocks.head().map_or(0, |head| head.timestamp() + 1));
+        block.set_nonce(nonce);
+        for transaction in transactions {
+            block.add_transaction(transaction);
+        }
+        set_state_root(bc, &mut block);
+        let block_clone = block.clone();
+        bc.append_block(block)?;
+
+        Ok(block_clone)
+    }
+
+    #[test]
+    fn test_height() {
+        let bc = &mut Blockchain::new();
+        assert_eq!(bc.height(), 0);
+
+        let genesis = append_block(bc, 1);
+        for nonce in 2..=4 {
+            append_block(bc, nonce);
+        }
+        assert_eq!(bc.height(), 4);
+        assert_eq!(bc.height(), bc.len());
+        assert_eq!(Some(bc.headers(1)[0].hash.clone()), genesis.hash);
+
+        bc.prune(2);
+        assert_eq!(bc.len(), 2);
+        assert_eq!(bc.height(), 4);
+
+        // Validation reports heights counted from genesis, not from the oldest kept block
+        bc.blocks.iter_mut().nth(1).unwrap().set_nonce(100);
+        assert_eq!(
+            bc.validate_all(),
+            vec![(
+                4,
+                "Block 4 prev_hash doesn't match Block 3 hash".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_last_block_none() {
+        assert_eq!(Blockchain::new().get_last_block_hash(), None);
+    }
+
+    #[test]
+    fn test_last_block() {
+        let mut bc = Blockchain::new();
+
+        append_block(&mut bc, 1);
+        append_block(&mut bc, 2);
+        let last_block = append_block(&mut bc, 3);
+        let last_block_hash = last_block.hash();
+
+        assert_eq!(bc.len(), 3);
+        assert_eq!(bc.get_last_block_hash(), Some(last_block_hash));
+    }
+
+    #[test]
+    fn test_append_block_returns_hash() {
+        let mut bc = Blockchain::new();
+        let mut block = Block::new(None);
+        block.set_nonce(1);
+        let hash = block.hash();
+
+        assert_eq!(bc.append_block(block), Ok(hash.clone()));
+        assert_eq!(bc.get_last_block_hash(), Some(hash));
+    }
+
+    #[test]
+    fn test_is_empty() {
+        let mut bc = Blockchain::new();
+        assert!(bc.is_empty());
+
+        append_block(&mut bc, 1);
+        assert!(!bc.is_empty());
+        assert_eq!(bc.len(), 1);
+    }
+
+    #[test]
+    fn test_resolve_short_hash() {
+        let bc = &mut Blockchain::new();
+        let blocks = (1..=9)
+            .map(|nonce| append_block(bc, nonce))
+            .collect::<Vec<_>>();
+        let tx_hash = blocks[3].transactions[0].hash();
+
+        assert_eq!(
+            bc.resolve_short_hash(&blocks[0].hash()[..20]),
+            Ok(blocks[0].hash())
+        );
+        assert_eq!(bc.resolve_short_hash(&tx_hash[..20]), Ok(tx_hash.clone()));
+        assert_eq!(bc.resolve_short_hash(&tx_hash.to_uppercase()), Ok(tx_hash));
+
+        // 18 hashes can't all start with a different hex digit
+        let hashes = bc
+            .blocks
+            .iter()
+            .map(|block| block.hash())
+            .chain(bc.iter_transactions().map(|tx| tx.hash()))
+            .collect::<Vec<_>>();
+        let shared = hashes
+            .iter()
+            .map(|hash| &hash[..1])
+            .find(|prefix| {
+                hashes
+                    .iter()
+                    .filter(|hash| hash.starts_with(*prefix))
+                    .count()
+                    > 1
+            })
+            .unwrap();
+        assert_eq!(
+            bc.resolve_short_hash(shared),
+            Err(format!("Ambiguous hash prefix: {}", shared))
+        );
+
+        assert_eq!(
+            bc.resolve_short_hash("not-hex"),
+            Err("Hash not found: not-hex".to_string())
+        );
+    }
+
+    #[test]
+    fn test_recent_blocks() {
+        let bc = &mut Blockchain::new();
+        assert!(bc.recent_blocks(3).is_empty());
+
+        let blocks = (1..=5)
+            .map(|nonce| append_block(bc, nonce))
+            .collect::<Vec<_>>();
+
+        let recent = bc.recent_blocks(3);
+        assert_eq!(recent.len(), 3);
+        // Heights 5, 4 and 3
+        for (block, height) in recent.into_iter().zip([5, 4, 3]) {
+            assert_eq!(block.hash(), blocks[height - 1].hash());
+        }
+        assert_eq!(bc.recent_blocks(10).len(), 5);
+    }
+
+    #[test]
+    fn test_get_block_by_hash() {
+        let mut bc = Blockchain::new();
+
+        let first_block = append_block(&mut bc, 1);
+        append_block(&mut bc, 2);
+
+        assert_eq!(
+            bc.get_block_by_hash(&first_block.hash())
+                .map(|block| block.hash()),
+            Some(first_block.hash())
+        );
+        assert!(bc.get_block_by_hash(&"unknown".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_confirmations() {
+        let bc = &mut Blockchain::new();
+        let tx = create_account_tx("alice".to_string());
+        append_block(bc, 1);
+        assert!(append_block_with_tx(bc, 2, vec![tx.clone()]).is_ok());
+        append_block(bc, 3);
+        append_block(bc, 4);
+        append_block(bc, 5);
+
+        assert_eq!(bc.confirmations(&tx.hash()), Some(3));
+        let head_tx = &bc.blocks.head().unwrap().transactions[0];
+        assert_eq!(bc.confirmations(&head_tx.hash()), Some(0));
+        assert_eq!(bc.confirmations(&"unknown".to_string()), None);
+
+        let path = std::env::temp_dir().join(format!(
+            "blockchain-test-{}.json",
+            generate_random_account()
+        ));
+        bc.save_to_file(&path).unwrap();
+        let loaded: Blockchain = Blockchain::load_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.confirmations(&tx.hash()), Some(3));
+
+        bc.prune(3);
+        assert_eq!(bc.confirmations(&tx.hash()), None);
+        let head_tx = bc.blocks.head().unwrap().transactions[0].clone();
+        assert_eq!(bc.confirmations(&head_tx.hash()), Some(0));
+    }
+
+    #[test]
+    fn test_average_block_time() {
+        let bc = &mut Blockchain::new();
+        assert_eq!(bc.average_block_time(), None);
+
+        for timestamp in [1000, 3000, 6000] {
+            let mut block = Block::new(bc.get_last_block_hash());
+            block.set_timestamp(timestamp);
+            block.add_transaction(create_account_tx(generate_random_account()));
+            set_state_root(bc, &mut block);
+            assert!(bc.append_block(block).is_ok());
+            if timestamp == 1000 {
+                assert_eq!(bc.average_block_time(), None);
+            }
+        }
+        assert_eq!(bc.average_block_time(), Some(2500.0));
+
+        // Zero genesis timestamp isn't a real one
+        let bc = &mut Blockchain::new();
+        append_block(bc, 1);
+        assert_eq!(bc.blocks.tail().unwrap().timestamp(), 0);
+        append_block(bc, 2);
+        assert_eq!(bc.average_block_time(), None);
+        append_block(bc, 3);
+        assert_eq!(bc.average_block_time(), Some(1.0));
+    }
+
+    #[test]
+    fn test_display() {
+        let bc = &mut Blockchain::new();
+        assert_eq!(
+            bc.to_string(),
+            "Blockchain height 0, head none, 0 accounts, total supply 0"
+        );
+
+        let genesis = append_block(bc, 1);
+        let head = append_block(bc, 2);
+        let output = bc.to_string();
+        let lines = output.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with(&format!(
+            "Blockchain height 2, head {}…, 2 accounts, total supply 0",
+            &head.hash()[..8]
+        )));
+        assert_eq!(lines[1], format!("  #1 {}", genesis));
+        assert!(lines[1].contains("prev none, 1 txs"));
+        assert!(lines[2].contains(&format!(
+            "  #2 Block {}… prev {}…, 1 txs, timestamp 1",
+            &head.hash()[..8],
+            &genesis.hash()[..8]
+        )));
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let bc = &mut Blockchain::new();
+        let genesis = append_block(bc, 1);
+        append_block(bc, 2);
+        let head = append_block(bc, 3);
+
+        let dot = bc.to_dot();
+        assert!(dot.starts_with("digraph blockchain {"));
+        assert_eq!(dot.matches("[label=").count(), 3);
+        assert_eq!(dot.matches(" -> ").count(), 2);
+        assert!(dot.contains(&format!("[label=\"{}\\n1 txs\"]", &genesis.hash()[..8])));
+        assert!(dot.contains(&format!(
+            "\"{}\" -> \"{}\"",
+            head.hash(),
+            head.prev_hash.unwrap()
+        )));
+    }
+
+    #[test]
+    fn test_iter_transactions() {
+        let bc = &mut Blockchain::new();
+        let first_tx = create_account_tx("alice".to_string());
+
+        assert!(append_block_with_tx(bc, 1, vec![first_tx.clone()]).is_ok());
+        assert!(append_block_with_tx(
+            bc,
+            2,
+            vec![
+                create_account_tx("bob".to_string()),
+                create_account_tx("carol".to_string()),
+            ],
+        )
+        .is_ok());
+        assert!(append_block_with_tx(
+            bc,
+            3,
+            vec![
+                create_account_tx("dave".to_string()),
+                create_account_tx("eve".to_string()),
+                create_account_tx("frank".to_string()),
+            ],
+        )
+        .is_ok());
+
+        assert_eq!(bc.iter_transactions().count(), 6);
+        assert_eq!(
+            bc.iter_transactions().next().map(|tx| tx.hash()),
+            Some(first_tx.hash())
+        );
+        assert_eq!(
+            bc.iter_transactions().last().map(|tx| tx.data.clone()),
+            Some(bc.blocks.head().unwrap().transactions[2].data.clone())
+        );
+    }
+
+    #[test]
+    fn test_transactions_for_account() {
+        let bc = &mut Blockchain::new();
+        let satoshi = Keypair::generate(&mut rand::rngs::OsRng {});
+        let alice = Keypair::generate(&mut rand::rngs::OsRng {});
+        assert!(append_block_with_tx(
+            bc,
+            1,
+            vec![
+                Transaction::new(
+                    TransactionData::CreateAccount("satoshi".to_string(), satoshi.public),
+                    None,
+                ),
+                Transaction::new(
+                    TransactionData::CreateAccount("alice".to_string(), alice.public),
+                    None,
+                ),
+                Transaction::new(
+                    TransactionData::MintInitialSupply {
+                        to: "satoshi".to_string(),
+                        amount: 100.into(),
+                    },
+                    None,
+                ),
+            ],
+        )
+        .is_ok());
+
+        let mut incoming = Transaction::new(
+            TransactionData::Transfer {
+                to: "alice".to_string(),
+                amount: 30.into(),
+                fee: 0.into(),
+            },
+            Some("satoshi".to_string()),
+        );
+        incoming.add_signature(sign_hash(&satoshi, bc.network_id(), &incoming.hash()));
+        assert!(append_block_with_tx(bc, 2, vec![incoming.clone()]).is_ok());
+
+        let mut outgoing = Transaction::new(
+            TransactionData::Transfer {
+                to: "satoshi".to_string(),
+                amount: 10.into(),
+                fee: 0.into(),
+            },
+            Some("alice".to_string()),
+        );
+        outgoing.add_signature(sign_hash(&alice, bc.network_id(), &outgoing.hash()));
+        assert!(append_block_with_tx(
+            bc,
+            3,
+            vec![create_account_tx("bob".to_string()), outgoing.clone()]
+        )
+        .is_ok());
+
+        let hashes = bc
+            .transactions_for_account(&"alice".to_string())
+            .iter()
+            .map(|tx| tx.hash())
+            .collect::<Vec<_>>();
+        assert_eq!(hashes.len(), 3);
+        assert_eq!(hashes[1], incoming.hash());
+        assert_eq!(hashes[2], outgoing.hash());
+        assert!(bc.transactions_for_account(&"carol".to_string()).is_empty());
+    }
+
+    #[test]
+    fn test_typed_iterators() {
+        let bc = &mut Blockchain::new();
+        let satoshi = Keypair::generate(&mut rand::rngs::OsRng {});
+        append_block_with_tx(
+            bc,
+            1,
+            vec![
+                Transaction::new(
+                    TransactionData::CreateAccount("satoshi".to_string(), satoshi.public),
+                    None,
+                ),
+                create_account_tx("alice".to_string()),
+                Transaction::new(
+                    TransactionData::MintInitialSupply {
+                        to: "satoshi".to_string(),
+                        amount: 100.into(),
+                    },
+                    None,
+                ),
+            ],
+        )
+        .unwrap();
+        let transfer = |to: &str, amount| {
+            TransactionBuilder::new(TransactionData::Transfer {
+                to: to.to_string(),
+                amount,
+                fee: 1.into(),
+            })
+            .from("satoshi".to_string())
+            .sign(&satoshi, bc.network_id())
+        };
+        append_block_with_tx(
+            bc,
+            2,
+            vec![
+                transfer("alice", 30.into()),
+                create_account_tx("bob".to_string()),
+                transfer("bob", 5.into()),
+            ],
+        )
+        .unwrap();
+
+        let (satoshi_id, alice_id, bob_id) = (
+            "satoshi".to_string(),
+            "alice".to_string(),
+            "bob".to_string(),
+        );
+        assert_eq!(
+            bc.transfers().collect::<Vec<_>>(),
+            vec![
+                (&satoshi_id, &alice_id, 30.into()),
+                (&satoshi_id, &bob_id, 5.into())
+            ]
+        );
+        assert_eq!(
+            bc.mints().collect::<Vec<_>>(),
+            vec![(&satoshi_id, 100.into())]
+        );
+        assert_eq!(
+            bc.created_accounts()
+                .map(|(account_id, _)| account_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["satoshi", "alice", "bob"]
+        );
+    }
+
+    #[test]
+    fn test_hasher() {
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let data = TransactionData::CreateAccount("satoshi".to_string(), keypair.public);
+
+        let mut blake2s = Blockchain::new();
+        let mut block = Block::new(None);
+        block.set_nonce(1);
+        block.add_transaction(Transaction::new(data.clone(), None));
+        assert!(blake2s.append_block(block).is_ok());
+
+        let mut sha256 = Blockchain::with_hasher(Sha256Hasher);
+        let mut block = Block::with_hasher(None, Sha256Hasher);
+        block.set_nonce(1);
+        block.add_transaction(Transaction::with_hasher(data, None, Sha256Hasher));
+        assert!(sha256.append_block(block).is_ok());
+        assert!(sha256
+            .add_to_pool(Transaction::with_hasher(
+                TransactionData::CreateAccount("alice".to_string(), keypair.public),
+                None,
+                Sha256Hasher,
+            ))
+            .is_ok());
+        assert!(sha256.mine_pending_transactions().is_ok());
+
+        assert_ne!(
+            blake2s.blocks.tail().map(|block| block.hash()),
+            sha256.blocks.tail().map(|block| block.hash())
+        );
+        assert!(blake2s.validate().is_ok());
+        assert!(sha256.validate().is_ok());
+    }
+
+    fn mine_block(bc: &Blockchain, min_zero_bits: u32, max_zero_bits: u32) -> Block {
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.set_timestamp(bc.blocks.head().map_or(0, |head| head.timestamp() + 1));
+        block.add_transaction(create_account_tx(generate_random_account()));
+        set_state_root(bc, &mut block);
+        let mut nonce = 0;
+        loop {
+            block.set_nonce(nonce);
+            let zero_bits = crate::utils::leading_zero_bits(&block.hash());
+            if (min_zero_bits..=max_zero_bits).contains(&zero_bits) {
+                return block;
+            }
+            nonce += 1;
+        }
+    }
+
+    #[test]
+    fn test_total_work() {
+        let mut bc = Blockchain::new();
+        assert_eq!(bc.total_work(), 0);
+
+        bc.append_block(mine_block(&bc, 0, 0)).unwrap();
+        bc.append_block(mine_block(&bc, 3, 3)).unwrap();
+
+        assert_eq!(bc.total_work(), 1 + 8);
+    }
+
+    #[test]
+    fn test_try_replace_chain_by_work() {
+        let mut genesis = Blockchain::new();
+        genesis.append_block(mine_block(&genesis, 0, 0)).unwrap();
+        let mut short_hard = genesis.clone();
+        for _ in 0..2 {
+            short_hard
+                .append_block(mine_block(&short_hard, 8, 256))
+                .unwrap();
+        }
+        let mut long_easy = genesis;
+        for _ in 0..3 {
+            long_easy
+                .append_block(mine_block(&long_easy, 0, 0))
+                .unwrap();
+        }
+        let short_hard_head = short_hard.get_last_block_hash();
+
+        assert_eq!(
+            short_hard.try_replace_chain(long_easy.clone()),
+            Err("Chain doesn't have more work.".to_string())
+        );
+        assert_eq!(short_hard.get_last_block_hash(), short_hard_head);
+
+        let dropped_tx = long_easy.blocks.head().unwrap().transactions[0].hash();
+        let genesis_tx = long_easy.blocks.tail().unwrap().transactions[0].hash();
+        assert!(long_easy.try_replace_chain(short_hard.clone()).is_ok());
+        assert_eq!(long_easy.len(), 3);
+        assert_eq!(long_easy.get_last_block_hash(), short_hard_head);
+        assert_eq!(long_easy.accounts.len(), 3);
+        assert_eq!(long_easy.confirmations(&dropped_tx), None);
+        assert_eq!(long_easy.confirmations(&genesis_tx), Some(2));
+    }
+
+    #[test]
+    fn test_max_reorg_depth() {
+        let extend = |bc: &Blockchain, blocks: usize| {
+            let mut bc = bc.clone();
+            for _ in 0..blocks {
+                bc.append_block(mine_block(&bc, 0, 0)).unwrap();
+            }
+            bc
+        };
+        let genesis = extend(&Blockchain::new(), 1);
+        let shared = extend(&genesis, 1);
+        let mut bc = extend(&shared, 2);
+        bc.consensus.max_reorg_depth = Some(2);
+        let head = bc.get_last_block_hash();
+
+        // Diverges right after genesis, so 3 of our blocks would be undone
+        let deep = extend(&genesis, 5);
+        assert_eq!(
+            bc.try_replace_chain(deep.clone()),
+            Err("Reorg is too deep: 3 > 2 blocks".to_string())
+        );
+        assert_eq!(bc.get_last_block_hash(), head);
+
+        let shallow = extend(&shared, 3);
+        assert!(bc.try_replace_chain(shallow.clone()).is_ok());
+        assert_eq!(bc.get_last_block_hash(), shallow.get_last_block_hash());
+
+        bc.consensus.max_reorg_depth = None;
+        assert!(bc.try_replace_chain(extend(&deep, 1)).is_ok());
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let bc = &mut Blockchain::new();
+        let events = bc.subscribe();
+        let dropped = bc.subscribe();
+        drop(dropped);
+
+        let block = append_block(bc, 1);
+        assert_eq!(
+            events.try_recv(),
+            Ok(ChainEvent::BlockAppended(block.hash()))
+        );
+
+        let tx = create_account_tx("alice".to_string());
+        bc.add_to_pool(tx.clone()).unwrap();
+        assert!(bc.add_to_pool(tx.clone()).is_err());
+        assert_eq!(
+            events.try_recv(),
+            Ok(ChainEvent::TransactionPooled(tx.hash()))
+        );
+        assert!(events.try_recv().is_err());
+        assert_eq!(bc.subscribers.len(), 1);
+
+        // Blocks of a replacing chain are announced, shared ones aren't
+        let mut longer = bc.clone();
+        longer.subscribers.clear();
+        let new_head = append_block(&mut longer, 2);
+        bc.try_replace_chain(longer).unwrap();
+        assert_eq!(
+            events.try_recv(),
+            Ok(ChainEvent::BlockAppended(new_head.hash()))
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_common_ancestor() {
+        let bc = &mut Blockchain::new();
+        append_block(bc, 1);
+        let shared = append_block(bc, 2);
+        let mut other = bc.clone();
+        // Blocks with the least work, so the longer chain always has more
+        let append_easy_block = |bc: &mut Blockchain| {
+            let mut block = mine_block(bc, 0, 0);
+            block.set_timestamp(bc.blocks.head().unwrap().timestamp() + 1);
+            while block.meets_difficulty(1) {
+                let nonce = block.header().nonce + 1;
+                block.set_nonce(nonce);
+            }
+            bc.append_block(block).unwrap();
+        };
+        append_easy_block(bc);
+        append_easy_block(&mut other);
+        append_easy_block(&mut other);
+        let other_blocks = other.blocks.clone().into_vec();
+
+        assert_eq!(bc.common_ancestor(&other_blocks), Some((2, shared.hash())));
+        assert_eq!(
+            bc.common_ancestor(&other_blocks[..1]),
+            Some((1, other_blocks[0].hash()))
+        );
+        assert_eq!(bc.common_ancestor(&[]), None);
+        assert_eq!(Blockchain::new().common_ancestor(&other_blocks), None);
+        assert_eq!(
+            other.common_ancestor(&other_blocks),
+            Some((4, other.get_last_block_hash().unwrap()))
+        );
+
+        // The fork is replayed from the ancestor, our third block is dropped
+        let state_hash = other.state_hash();
+        bc.try_replace_chain(other.clone()).unwrap();
+        assert_eq!(bc.get_last_block_hash(), other.get_last_block_hash());
+        assert_eq!(bc.state_hash(), state_hash);
+        assert_eq!(bc.validate(), Ok(()));
+
+        other.prune(2);
+        assert_eq!(
+            other.common_ancestor(&bc.blocks.clone().into_vec()),
+            Some((4, other.get_last_block_hash().unwrap()))
+        );
+        assert_eq!(other.common_ancestor(&other_blocks[..2]), None);
+    }
+
+    #[test]
+    fn test_chain_id() {
+        let mut genesis = Block::genesis();
+        genesis.add_transaction(create_account_tx("satoshi".to_string()));
+
+        let mut bc = Blockchain::new();
+        assert_eq!(bc.chain_id(), None);
+        bc.append_block(genesis.clone()).unwrap();
+        let mut same = Blockchain::new();
+        same.append_block(genesis.clone()).unwrap();
+        append_block(&mut same, 2);
+        let mut other = Blockchain::new();
+        append_block(&mut other, 1);
+
+        assert_eq!(bc.chain_id(), Some(genesis.hash()));
+        assert_eq!(same.chain_id(), bc.chain_id());
+        assert_ne!(other.chain_id(), bc.chain_id());
+
+        append_block(&mut other, 2);
+        append_block(&mut other, 3);
+        assert_eq!(
+            bc.try_replace_chain(other),
+            Err("Chain has a different genesis.".to_string())
+        );
+        assert!(bc.try_replace_chain(same).is_ok());
+    }
+
+    #[test]
+    fn test_default_network_id() {
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let setup = |nonce| {
+            let mut genesis = Block::genesis();
+            genesis.set_nonce(nonce);
+            genesis.add_transaction(Transaction::new(
+                TransactionData::CreateAccount("alice".to_string(), keypair.public),
+                None,
+            ));
+            let mut bc = Blockchain::new();
+            bc.append_block(genesis).unwrap();
+            bc
+        };
+        let mut chain_a = setup(1);
+        let mut chain_b = setup(2);
+        assert_eq!(chain_a.network_id(), chain_a.chain_id().unwrap());
+        assert_ne!(chain_a.network_id(), chain_b.network_id());
+
+        let tx = TransactionBuilder::new(TransactionData::SetMetadata {
+            key: "name".to_string(),
+            value: "alice".to_string(),
+        })
+        .from("alice".to_string())
+        .sign(&keypair, chain_a.network_id());
+        assert_eq!(
+            tx.clone().execute(&mut chain_b, false),
+            Err("Invalid signature.".to_string())
+        );
+        assert!(tx.execute(&mut chain_a, false).is_ok());
+
+        chain_a.network_id = "chain-a".to_string();
+        assert_eq!(chain_a.network_id(), "chain-a");
+    }
+
+    #[test]
+    fn test_validate() {
+        let bc = &mut Blockchain::new();
+
+        append_block(bc, 1);
+        append_block(bc, 2);
+        append_block(bc, 3);
+        bc.blocks.iter_mut().next().unwrap().transactions[0] =
+            create_account_tx("malicios user".to_string());
+
+        assert_eq!(bc.validate(), Err(String::from("Block 3 has invalid hash")));
+    }
+
+    #[test]
+    fn test_validate_transaction_set() {
+        let bc = &mut Blockchain::new();
+        let satoshi = Keypair::generate(&mut rand::rngs::OsRng {});
+        let transfer = |amount| {
+            TransactionBuilder::new(TransactionData::Transfer {
+                to: "alice".to_string(),
+                amount,
+                fee: 0.into(),
+            })
+            .from("satoshi".to_string())
+            .sign(&satoshi, bc.network_id())
+        };
+        let txs = vec![
+            Transaction::new(
+                TransactionData::CreateAccount("satoshi".to_string(), satoshi.public),
+                None,
+            ),
+            create_account_tx("alice".to_string()),
+            transfer(30.into()),
+            Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: "satoshi".to_string(),
+                    amount: 100.into(),
+                },
+                None,
+            ),
+            transfer(30.into()),
+            transfer(80.into()),
+            create_account_tx("alice".to_string()),
+        ];
+
+        assert_eq!(
+            bc.validate_transaction_set(&txs),
+            vec![
+                Ok(()),
+                Ok(()),
+                Err("Insufficient balance".to_string()),
+                Ok(()),
+                Ok(()),
+                Err("Insufficient balance".to_string()),
+                Err("AccountId already exist: alice".to_string()),
+            ]
+        );
+        assert!(bc.accounts.is_empty());
+        assert!(bc.is_empty());
+    }
+
+    #[test]
+    fn test_pending_balance_of() {
+        let bc = &mut Blockchain::new();
+        let alice = Keypair::generate(&mut rand::rngs::OsRng {});
+        assert!(append_block_with_tx(
+            bc,
+            1,
+            vec![
+                Transaction::new(
+                    TransactionData::CreateAccount("alice".to_string(), alice.public),
+                    None,
+                ),
+                create_account_tx("bob".to_string()),
+                Transaction::new(
+                    TransactionData::MintInitialSupply {
+                        to: "alice".to_string(),
+                        amount: 100.into(),
+                    },
+                    None,
+                ),
+            ],
+        )
+        .is_ok());
+
+        let alice_id = "alice".to_string();
+        let bob_id = "bob".to_string();
+        let network_id = bc.network_id().to_string();
+        let transfer = |amount, nonce| {
+            TransactionBuilder::new(TransactionData::Transfer {
+                to: bob_id.clone(),
+                amount,
+                fee: 0.into(),
+            })
+            .from(alice_id.clone())
+            .nonce(nonce)
+            .sign(&alice, &network_id)
+        };
+        bc.add_to_pool(transfer(30.into(), 1)).unwrap();
+
+        assert_eq!(bc.pending_balance_of(&alice_id), 70);
+        assert_eq!(bc.pending_balance_of(&bob_id), 30);
+        assert_eq!(bc.get_account_by_id(&alice_id).unwrap().balance, 100);
+        assert_eq!(bc.pending_balance_of(&"carol".to_string()), 0);
+
+        // The pool rejects overdrafts, but the view never underflows
+        bc.transactions_pool.push(transfer(100.into(), 2));
+        assert_eq!(bc.pending_balance_of(&alice_id), 0);
+    }
+
+    #[test]
+    fn test_validate_all() {
+        let bc = &mut Blockchain::new();
+
+        append_block(bc, 1);
+        append_block(bc, 2);
+        append_block(bc, 3);
+        append_block(bc, 4);
+        bc.blocks.iter_mut().next().unwrap().transactions[0] =
+            create_account_tx("malicios user".to_string());
+        bc.blocks.iter_mut().nth(2).unwrap().transactions[0] =
+            create_account_tx("malicios user".to_string());
+
+        assert_eq!(
+            bc.validate_all(),
+            vec![
+                (4, "Block 4 has invalid hash".to_string()),
+                (2, "Block 2 has invalid hash".to_string()),
+                (
+                    2,
+                    "Block 2 can't be replayed: Invalid account id: \"malicios user\" has illegal characters".to_string()
+                ),
+            ]
+        );
+        assert_eq!(bc.validate(), Err("Block 4 has invalid hash".to_string()));
+    }
+
+    #[test]
+    fn test_validate_timestamps() {
+        let bc = &mut Blockchain::new();
+
+        for timestamp in [10, 20, 30, 40] {
+            let mut block = Block::new(bc.get_last_block_hash());
+            block.set_timestamp(timestamp);
+            block.add_transaction(create_account_tx(generate_random_account()));
+            set_state_root(bc, &mut block);
+            assert!(bc.append_block(block).is_ok());
+        }
+        let mut stale = Block::new(bc.get_last_block_hash());
+        stale.set_timestamp(40);
+        stale.add_transaction(create_account_tx(generate_random_account()));
+        set_state_root(bc, &mut stale);
+        assert_eq!(
+            bc.append_block(stale),
+            Err("Block timestamp 40 isn't after the head's 40".to_string())
+        );
+        assert_eq!(bc.validate_all(), vec![]);
+
+        // Moving block 3 back in time, a forger has to rehash it
+        let block = bc.blocks.iter_mut().nth(1).unwrap();
+        block.set_timestamp(15);
+        block.hash = Some(block.hash());
+
+        assert_eq!(
+            bc.validate_all(),
+            vec![
+                (
+                    4,
+                    "Block 4 prev_hash doesn't match Block 3 hash".to_string()
+                ),
+                (3, "Non-monotonic timestamp at block 3".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_checkpoint() {
+        let bc = &mut Blockchain::new();
+
+        append_block(bc, 1);
+        let checkpoint = append_block(bc, 2);
+        append_block(bc, 3);
+        bc.set_checkpoint(2, checkpoint.hash());
+        // Blocks below the checkpoint aren't validated
+        bc.blocks.iter_mut().last().unwrap().transactions[0] =
+            create_account_tx("malicios user".to_string());
+        assert_eq!(bc.validate(), Ok(()));
+
+        bc.set_checkpoint(3, "unknown".to_string());
+        assert_eq!(
+            bc.validate(),
+            Err(String::from("Block 3 doesn't match checkpoint"))
+        );
+    }
+
+    #[test]
+    fn test_validate_checkpoint_mismatch() {
+        let bc = &mut Blockchain::new();
+
+        append_block(bc, 1);
+        append_block(bc, 2);
+        append_block(bc, 3);
+        bc.set_checkpoint(2, "unknown".to_string());
+
+        assert_eq!(
+            bc.validate(),
+            Err(String::from("Block 2 doesn't match checkpoint"))
+        );
+    }
+
+    #[test]
+    fn test_genesis_marker() {
+        let bc = &mut Blockchain::new();
+        let mut block = Block::new(Some("unknown".to_string()));
+        block.set_nonce(1);
+        assert_eq!(
+            bc.append_block(block),
+            Err("First block must be genesis.".to_string())
+        );
+
+        append_block(bc, 1);
+        let mut block = Block::genesis();
+        block.add_transaction(create_account_tx("alice".to_string()));
+        assert_eq!(
+            bc.append_block(block),
+            Err("Unexpected genesis: block without prev_hash.".to_string())
+        );
+        assert_eq!(bc.len(), 1);
+
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.is_genesis = true;
+        block.add_transaction(create_account_tx("alice".to_string()));
+        assert_eq!(
+            bc.append_block(block),
+            Err("Only the first block can be genesis.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unexpected_genesis() {
+        let bc = &mut Blockchain::new();
+        append_block(bc, 1);
+
+        // Not marked as genesis, but without a parent
+        let mut block = Block::new(Some("unknown".to_string()));
+        block.prev_hash = None;
+        block.add_transaction(create_account_tx("alice".to_string()));
+        assert!(!block.is_genesis());
+        assert_eq!(
+            bc.append_block(block),
+            Err("Unexpected genesis: block without prev_hash.".to_string())
+        );
+        assert_eq!(bc.len(), 1);
+    }
+
+    #[test]
+    fn test_check_block() {
+        let bc = &mut Blockchain::new();
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        assert!(append_block_with_tx(
+            bc,
+            1,
+            vec![
+                Transaction::new(
+                    TransactionData::CreateAccount("satoshi".to_string(), keypair.public),
+                    None,
+                ),
+                create_account_tx("alice".to_string()),
+                Transaction::new(
+                    TransactionData::MintInitialSupply {
+                        to: "satoshi".to_string(),
+                        amount: 100.into(),
+                    },
+                    None,
+                ),
+            ],
+        )
+        .is_ok());
+        let spend = |nonce| {
+            TransactionBuilder::new(TransactionData::Transfer {
+                to: "alice".to_string(),
+                amount: 60.into(),
+                fee: 0.into(),
+            })
+            .from("satoshi".to_string())
+            .nonce(nonce)
+            .sign(&keypair, bc.network_id())
+        };
+
+        let mut block = Block::new(bc.get_last_block_hash());
+
+        block.set_timestamp(1);
+        block.add_transaction(spend(1));
+        set_state_root(bc, &mut block);
+        assert_eq!(bc.check_block(&block), Ok(()));
+        block.add_transaction(spend(2));
+        assert_eq!(
+            bc.check_block(&block),
+            Err("Error during executing transactions: Insufficient balance".to_string())
+        );
+
+        let mut detached = Block::new(Some("unknown".to_string()));
+        detached.add_transaction(spend(1));
+        assert_eq!(
+            bc.check_block(&detached),
+            Err("Block doesn't extend the head.".to_string())
+        );
+
+        assert_eq!(bc.len(), 1);
+        assert_eq!(
+            bc.get_account_by_id(&"satoshi".to_string())
+                .unwrap()
+                .balance,
+            100
+        );
+        assert_eq!(
+            bc.get_account_by_id(&"alice".to_string()).unwrap().balance,
+            0
+        );
+    }
+
+    #[test]
+    fn test_genesis_policy() {
+        let bc = &mut Blockchain::new();
+        bc.genesis_policy = GenesisPolicy {
+            require_account: true,
+            total_mint: Some(100.into()),
+        };
+        let mint = |amount| {
+            Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: "satoshi".to_string(),
+                    amount,
+                },
+                None,
+            )
+        };
+
+        assert_eq!(
+            append_block_with_tx(
+                bc,
+                1,
+                vec![create_account_tx("satoshi".to_string()), mint(150.into())]
+            )
+            .err(),
+            Some("Genesis mints 150 instead of 100".to_string())
+        );
+        assert!(bc.is_empty());
+        assert!(append_block_with_tx(
+            bc,
+            1,
+            vec![create_account_tx("satoshi".to_string()), mint(100.into())]
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_validate_genesis_marker() {
+        let bc = &mut Blockchain::new();
+        append_block(bc, 1);
+        append_block(bc, 2);
+
+        let block = bc.blocks.iter_mut().next().unwrap();
+        block.is_genesis = true;
+        block.hash = Some(block.hash());
+        assert_eq!(
+            bc.validate(),
+            Err("Block 2 shouldn't be genesis".to_string())
+        );
+
+        let bc = &mut Blockchain::new();
+        append_block(bc, 1);
+        let block = bc.blocks.iter_mut().next().unwrap();
+        block.is_genesis = false;
+        block.hash = Some(block.hash());
+        assert_eq!(bc.validate(), Err("First block isn't genesis".to_string()));
+    }
+
+    #[test]
+    fn test_validate_prev_hash() {
+        let bc = &mut Blockchain::new();
+
+        append_block(bc, 1);
+        append_block(bc, 2);
+        let block = bc.blocks.iter_mut().next().unwrap();
+        block.prev_hash = Some("invalid_prev_hash".to_string());
+        block.hash = Some(block.hash());
+
+        assert_eq!(
+            bc.validate(),
+            Err(String::from("Block 2 prev_hash doesn't match Block 1 hash"))
+        );
+    }
+
+    #[test]
+    fn test_validate_prev_hash_none() {
+        let bc = &mut Blockchain::new();
+
+        append_block(bc, 1);
+        append_block(bc, 2);
+        let block = bc.blocks.iter_mut().next().unwrap();
+        block.prev_hash = None;
+        block.hash = Some(block.hash());
+
+        assert_eq!(
+            bc.validate(),
+            Err(String::from("Block 2 doesn't have prev_hash"))
+        );
+    }
+
+    #[test]
+    fn test_headers() {
+        let bc = &mut Blockchain::new();
+        let blocks = (1..=4)
+            .map(|nonce| append_block(bc, nonce))
+            .collect::<Vec<_>>();
+
+        let headers = bc.headers(1);
+        assert_eq!(headers.len(), 4);
+        assert_eq!(headers[0], blocks[0].header());
+        assert_eq!(headers[3].hash, bc.get_last_block_hash().unwrap());
+        assert!(headers.iter().all(|header| header.work() >= 1));
+        assert_eq!(BlockHeader::check_links(&headers), Ok(()));
+        assert_eq!(bc.headers(3), headers[2..].to_vec());
+        assert_eq!(bc.headers(0), headers);
+        assert!(bc.headers(5).is_empty());
+
+        let mut tampered = headers.clone();
+        tampered[1].hash = "tampered".to_string();
+        assert_eq!(
+            BlockHeader::check_links(&tampered),
+            Err("Header 2 doesn't link to header 1".to_string())
+        );
+
+        bc.prune(2);
+        assert_eq!(bc.headers(1), headers[2..].to_vec());
+        assert_eq!(bc.headers(4), headers[3..].to_vec());
+    }
+
+    #[test]
+    fn test_state_root() {
+        let bc = &mut Blockchain::new();
+        bc.add_to_pool(create_account_tx("satoshi".to_string()))
+            .unwrap();
+        bc.add_to_pool(Transaction::new(
+            TransactionData::MintInitialSupply {
+                to: "satoshi".to_string(),
+                amount: 100.into(),
+            },
+            None,
+        ))
+        .unwrap();
+        bc.mine_pending_transactions().unwrap();
+
+        let head = bc.blocks.head().unwrap();
+        assert_eq!(head.state_root(), Some(&bc.state_hash()));
+        assert_eq!(bc.validate(), Ok(()));
+
+        // A block committing to another state is rejected and rolled back
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.set_timestamp(head.timestamp() + 1);
+        block.add_transaction(create_account_tx("alice".to_string()));
+        block.set_state_root(bc.state_hash());
+        let error = bc.check_block(&block).unwrap_err();
+        assert!(error.starts_with("State root mismatch"));
+        assert_eq!(bc.append_block(block), Err(error));
+        assert!(!bc.accounts.contains_key("alice"));
+
+        bc.accounts.get_mut("satoshi").unwrap().balance = 1_000_000.into();
+        assert_eq!(
+            bc.validate(),
+            Err("Block 1 state root doesn't match the state".to_string())
+        );
+    }
+
+    #[test]
+    fn test_state_root_required() {
+        let bc = &mut Blockchain::new();
+        append_block(bc, 1);
+        append_block(bc, 2);
+
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.set_timestamp(2);
+        block.add_transaction(create_account_tx("alice".to_string()));
+        assert_eq!(
+            bc.append_block(block.clone()),
+            Err("Block has no state root.".to_string())
+        );
+
+        // Slipped in without checks, only replaying finds the wrong root
+        block.set_state_root(bc.state_hash());
+        block.add_transaction(create_account_tx("bob".to_string()));
+        for transaction in block.transactions.iter() {
+            transaction.execute(bc, false).unwrap();
+        }
+        bc.blocks.append(block);
+        append_block(bc, 4);
+
+        assert_eq!(
+            bc.validate_all(),
+            vec![(3, "Block 3 state root doesn't match the state".to_string())]
+        );
+        assert_eq!(
+            bc.replay_state(),
+            Err("Error replaying block 3: state root doesn't match the state".to_string())
+        );
+    }
+
+    #[test]
+    fn test_mine_pending_transactions() {
+        let bc = &mut Blockchain::new();
+        assert!(bc
+            .add_to_pool(create_account_tx("satoshi".to_string()))
+            .is_ok());
+        assert!(bc
+            .add_to_pool(Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: "satoshi".to_string(),
+                    amount: 100.into(),
+                },
+                None,
+            ))
+            .is_ok());
+
+        assert!(bc.mine_pending_transactions().is_ok());
+        assert_eq!(bc.len(), 1);
+        assert!(bc.transactions_pool.is_empty());
+        assert_eq!(
+            bc.get_account_by_id(&"satoshi".to_string())
+                .unwrap()
+                .balance,
+            100
+        );
+
+        assert_eq!(
+            bc.mine_pending_transactions(),
+            Err("Block has 0 transaction.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_mine_by_fee() {
+        let bc = &mut Blockchain::new();
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        append_block_with_tx(
+            bc,
+            1,
+            vec![
+                Transaction::new(
+                    TransactionData::CreateAccount("satoshi".to_string(), keypair.public),
+                    None,
+                ),
+                Transaction::new(
+                    TransactionData::MintInitialSupply {
+                        to: "satoshi".to_string(),
+                        amount: 100.into(),
+                    },
+                    None,
+                ),
+            ],
+        )
+        .unwrap();
+
+        let network_id = bc.network_id().to_string();
+        let transfer = |fee| {
+            TransactionBuilder::new(TransactionData::Transfer {
+                to: "alice".to_string(),
+                amount: 10.into(),
+                fee,
+            })
+            .from("satoshi".to_string())
+            .sign(&keypair, &network_id)
+        };
+        // The account creation has no fee, but transfers to alice depend on it
+        assert!(bc
+            .add_to_pool(create_account_tx("alice".to_string()))
+            .is_ok());
+        for fee in [5, 1, 3] {
+            assert!(bc.add_to_pool(transfer(fee.into())).is_ok());
+        }
+        assert_eq!(
+            bc.transactions_pool
+                .iter()
+                .map(|tx| tx.fee())
+                .collect::<Vec<_>>(),
+            vec![5, 3, 1, 0]
+        );
+
+        assert!(bc.mine_pending_transactions().is_ok());
+        assert!(bc.transactions_pool.is_empty());
+        let block = bc.blocks.head().unwrap();
+        assert_eq!(
+            block
+                .transactions
+                .iter()
+                .map(|tx| tx.fee())
+                .collect::<Vec<_>>(),
+            vec![0, 5, 3, 1]
+        );
+        assert_eq!(bc.accounts["alice"].balance, 30);
+        assert_eq!(bc.accounts["satoshi"].balance, 100 - 30 - 9);
+    }
+
+    #[test]
+    fn test_mine_block_limit() {
+        let bc = &mut Blockchain::new();
+        for i in 0..MAX_TXS_PER_BLOCK + 1 {
+            assert!(bc
+                .add_to_pool(create_account_tx(format!("account{}", i)))
+                .is_ok());
+        }
+
+        assert!(bc.mine_pending_transactions().is_ok());
+        assert_eq!(
+            bc.blocks.head().unwrap().transactions_len(),
+            MAX_TXS_PER_BLOCK
+        );
+        assert_eq!(bc.transactions_pool.len(), 1);
+        assert!(bc.mine_pending_transactions().is_ok());
+        assert!(bc.transactions_pool.is_empty());
+    }
+
+    #[test]
+    fn test_submit_block() {
+        let bc = &mut Blockchain::new();
+        append_block(bc, 1);
+        let mut source = bc.clone();
+        let parent = append_block(&mut source, 2);
+        let child = append_block(&mut source, 3);
+
+        assert_eq!(bc.submit_block(child.clone()), Ok(0));
+        assert_eq!(bc.submit_block(child.clone()), Ok(0));
+        assert_eq!(bc.orphans().len(), 1);
+        assert_eq!(bc.len(), 1);
+
+        assert_eq!(bc.submit_block(parent.clone()), Ok(2));
+        assert_eq!(bc.len(), 3);
+        assert_eq!(bc.get_last_block_hash(), Some(child.hash()));
+        assert!(bc.orphans().is_empty());
+        assert_eq!(bc.validate(), Ok(()));
+
+        // A known parent that isn't the head is a fork, not an orphan
+        assert_eq!(
+            bc.submit_block(parent),
+            Err("Block doesn't extend the head.".to_string())
+        );
+        assert!(bc.orphans().is_empty());
+    }
+
+    #[test]
+    fn test_consensus_config() {
+        let bc = &mut Blockchain::new();
+        bc.consensus = ConsensusConfig {
+            difficulty: 2,
+            max_txs_per_block: 2,
+            ..Default::default()
+        };
+        for i in 0..3 {
+            bc.add_to_pool(create_account_tx(format!("account{}", i)))
+                .unwrap();
+        }
+        bc.mine_pending_transactions().unwrap();
+        let head = bc.blocks.head().unwrap();
+        assert_eq!(head.transactions_len(), 2);
+        assert!(head.meets_difficulty(2));
+
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.set_timestamp(head.timestamp() + 1);
+        for i in 3..6 {
+            block.add_transaction(create_account_tx(format!("account{}", i)));
+        }
+        set_state_root(bc, &mut block);
+        block.mine_in_range(2, 0, u128::MAX);
+        assert_eq!(
+            bc.append_block(block.clone()),
+            Err("Block has too many transactions: 3 > 2".to_string())
+        );
+
+        bc.consensus.max_txs_per_block = 3;
+        bc.consensus.difficulty = 64;
+        assert_eq!(
+            bc.append_block(block.clone()),
+            Err("Block doesn't meet the difficulty of 64 bits".to_string())
+        );
+        bc.consensus.difficulty = 2;
+        assert!(bc.append_block(block).is_ok());
+    }
+
+    #[test]
+    fn test_spoofed_hash() {
+        let bc = &mut Blockchain::new();
+        bc.consensus.difficulty = 4;
+        let mut block = Block::genesis();
+        block.add_transaction(create_account_tx("alice".to_string()));
+        let mut nonce = 0;
+        while block.meets_difficulty(4) {
+            nonce += 1;
+            block.set_nonce(nonce);
+        }
+
+        // A stored hash with plenty of work doesn't stand in for the real one
+        let mut spoofed = block.clone();
+        spoofed.hash = Some("0".repeat(64));
+        assert_eq!(
+            bc.append_block(spoofed.clone()),
+            Err("Block has invalid hash".to_string())
+        );
+        assert_eq!(
+            bc.check_block(&spoofed),
+            Err("Block has invalid hash".to_string())
+        );
+        spoofed.hash = None;
+        assert!(bc.append_block(spoofed).is_err());
+        assert!(bc.is_empty());
+
+        assert_eq!(
+            bc.append_block(block.clone()),
+            Err("Block doesn't meet the difficulty of 4 bits".to_string())
+        );
+        block.mine_in_range(4, 0, u128::MAX);
+        assert_eq!(bc.append_block(block.clone()), Ok(block.hash()));
+        assert_eq!(bc.get_last_block_hash(), Some(block.hash()));
+    }
+
+    #[test]
+    fn test_oversized_miner_note() {
+        let bc = &mut Blockchain::new();
+        append_block(bc, 1);
+
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.set_timestamp(1);
+        block.add_transaction(create_account_tx("alice".to_string()));
+        block.miner_note = vec![0; MAX_MINER_NOTE_LEN + 1];
+        block.set_nonce(2);
+        set_state_root(bc, &mut block);
+        assert_eq!(
+            bc.append_block(block.clone()),
+            Err("Miner note is too long: 65 > 64".to_string())
+        );
+
+        block.set_miner_note(b"note".to_vec()).unwrap();
+        assert_eq!(bc.append_block(block.clone()), Ok(block.hash()));
+        assert_eq!(bc.blocks.head().unwrap().miner_note(), b"note");
+    }
+
+    #[test]
+    fn test_max_txs_per_account() {
+        let (ref mut bc, keypairs) =
+            bootstrap_chain(&[("satoshi", 100.into()), ("alice", 0.into())]);
+        let satoshi = &keypairs["satoshi"];
+        let network_id = bc.network_id().to_string();
+        let transfer = |amount: u128| {
+            TransactionBuilder::new(TransactionData::Transfer {
+                to: "alice".to_string(),
+                amount: amount.into(),
+                fee: 0.into(),
+            })
+            .from("satoshi".to_string())
+            .sign(satoshi, &network_id)
+        };
+        bc.consensus.max_txs_per_account = Some(2);
+
+        assert_eq!(
+            append_block_with_tx(bc, 2, vec![transfer(1), transfer(2), transfer(3)]),
+            Err("Too many transactions from satoshi: 3 > 2".to_string())
+        );
+        assert_eq!(bc.balance_of(&"alice".to_string()), Some(0.into()));
+
+        for amount in 1..=3 {
+            bc.add_to_pool(transfer(amount)).unwrap();
+        }
+        bc.add_to_pool(create_account_tx("bob".to_string()))
+            .unwrap();
+        bc.mine_pending_transactions().unwrap();
+        assert_eq!(bc.blocks.head().unwrap().transactions_len(), 3);
+        assert_eq!(bc.transactions_pool.len(), 1);
+        assert_eq!(bc.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_expired_transaction() {
+        let bc = &mut Blockchain::new();
+        append_block(bc, 1);
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let create_account = |valid_until| {
+            TransactionBuilder::new(TransactionData::CreateAccount(
+                "alice".to_string(),
+                keypair.public,
+            ))
+            .valid_until(valid_until)
+            .sign(&keypair, bc.network_id())
+        };
+
+        let tx = create_account(1000);
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.set_timestamp(1001);
+        block.add_transaction(tx.clone());
+        set_state_root(bc, &mut block);
+        assert_eq!(
+            bc.append_block(block),
+            Err(format!("Transaction expired: {}", tx.hash()))
+        );
+        assert!(!bc.accounts.contains_key("alice"));
+
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.set_timestamp(1000);
+        block.add_transaction(tx);
+        set_state_root(bc, &mut block);
+        assert!(bc.append_block(block).is_ok());
+        assert!(bc.accounts.contains_key("alice"));
+    }
+
+    #[test]
+    fn test_evict_expired() {
+        let bc = &mut Blockchain::new();
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let now = current_timestamp();
+
+        assert!(bc
+            .add_to_pool(
+                TransactionBuilder::new(TransactionData::CreateAccount(
+                    "alice".to_string(),
+                    keypair.public,
+                ))
+                .valid_until(now - 1)
+                .sign(&keypair, bc.network_id())
+            )
+            .is_err());
+        assert!(bc
+            .add_to_pool(
+                TransactionBuilder::new(TransactionData::CreateAccount(
+                    "alice".to_string(),
+                    keypair.public,
+                ))
+                .valid_until(now + 60_000)
+                .sign(&keypair, bc.network_id())
+            )
+            .is_ok());
+        assert!(bc.add_to_pool(create_account_tx("bob".to_string())).is_ok());
+
+        bc.evict_expired(now + 60_000);
+        assert_eq!(bc.transactions_pool.len(), 2);
+        bc.evict_expired(now + 60_001);
+        assert_eq!(bc.transactions_pool.len(), 1);
+        assert!(bc.transactions_pool[0].valid_until().is_none());
+    }
+
+    #[test]
+    fn test_block_too_large() {
+        let bc = &mut Blockchain::new();
+        let mut block = Block::genesis();
+        let mut i = 0;
+        while block.size_bytes() <= MAX_BLOCK_BYTES {
+            block.add_transaction(create_account_tx(format!("account{}", i)));
+            i += 1;
+        }
+
+        assert_eq!(
+            bc.append_block(block.clone()),
+            Err(format!(
+                "Block is too large: {} > {} bytes",
+                block.size_bytes(),
+                MAX_BLOCK_BYTES
+            ))
+        );
+        assert!(bc.accounts.is_empty());
+    }
+
+    #[test]
+    fn test_mine_block_bytes_limit() {
+        let bc = &mut Blockchain::new();
+        // Longest ids, so that the bytes limit is hit before MAX_TXS_PER_BLOCK
+        let account_id = |i| format!("{:064}", i);
+        let tx_count = MAX_BLOCK_BYTES / create_account_tx(account_id(0)).size_bytes() + 1;
+        assert!(tx_count <= MAX_TXS_PER_BLOCK);
+        for i in 0..tx_count {
+            assert!(bc.add_to_pool(create_account_tx(account_id(i))).is_ok());
+        }
+
+        assert!(bc.mine_pending_transactions().is_ok());
+        let block = bc.blocks.head().unwrap();
+        assert!(block.size_bytes() <= MAX_BLOCK_BYTES);
+        assert!(block.transactions_len() < tx_count);
+        assert_eq!(
+            bc.transactions_pool.len(),
+            tx_count - block.transactions_len()
+        );
+    }
+
+    #[test]
+    fn test_add_to_pool_evicts_lowest_fee() {
+        let bc = &mut Blockchain::new();
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        assert!(append_block_with_tx(
+            bc,
+            1,
+            vec![
+                Transaction::new(
+                    TransactionData::CreateAccount("satoshi".to_string(), keypair.public),
+                    None,
+                ),
+                create_account_tx("alice".to_string()),
+                Transaction::new(
+                    TransactionData::MintInitialSupply {
+                        to: "satoshi".to_string(),
+                        amount: 100.into(),
+                    },
+                    None,
+                ),
+            ],
+        )
+        .is_ok());
+        bc.max_pool_size = Some(3);
+        let network_id = bc.network_id().to_string();
+        let transfer = |fee, nonce| {
+            TransactionBuilder::new(TransactionData::Transfer {
+                to: "alice".to_string(),
+                amount: 1.into(),
+                fee,
+            })
+            .from("satoshi".to_string())
+            .nonce(nonce)
+            .sign(&keypair, &network_id)
+        };
+
+        for nonce in 0..3 {
+            assert_eq!(bc.add_to_pool(transfer(1.into(), nonce)), Ok(None));
+        }
+        assert_eq!(
+            bc.add_to_pool(transfer(1.into(), 3)),
+            Err("Transaction pool is full.".to_string())
+        );
+
+        let evicted = bc.add_to_pool(transfer(5.into(), 4)).unwrap().unwrap();
+        assert_eq!(evicted.fee(), 1);
+        assert_eq!(bc.transactions_pool.len(), 3);
+        assert_eq!(bc.transactions_pool[0].fee(), 5);
+        assert!(!bc.transactions_pool.contains(&evicted));
+    }
+
+    #[test]
+    fn test_add_to_pool_keeps_dependencies() {
+        let (mut bc, keypairs) = bootstrap_chain(&[
+            ("satoshi", 100.into()),
+            ("alice", 0.into()),
+            ("bob", 0.into()),
+        ]);
+        bc.max_pool_size = Some(3);
+        let network_id = bc.network_id().to_string();
+        let transfer = |from: &str, to: &str, amount: u128, fee: u128, nonce| {
+            TransactionBuilder::new(TransactionData::Transfer {
+                to: to.to_string(),
+                amount: amount.into(),
+                fee: fee.into(),
+            })
+            .from(from.to_string())
+            .nonce(nonce)
+            .sign(&keypairs[from], &network_id)
+        };
+        let fund_alice = transfer("satoshi", "alice", 10, 1, 0);
+        let from_alice = transfer("alice", "bob", 5, 3, 0);
+        let to_bob = transfer("satoshi", "bob", 1, 2, 1);
+        for tx in [&fund_alice, &from_alice, &to_bob] {
+            assert_eq!(bc.add_to_pool(tx.clone()), Ok(None));
+        }
+
+        // Alice's transfer needs her funding, so the next lowest fee goes
+        let evicted = bc.add_to_pool(transfer("satoshi", "bob", 1, 4, 2)).unwrap();
+        assert_eq!(evicted, Some(to_bob));
+        assert_eq!(bc.transactions_pool.len(), 3);
+        assert!(bc.transactions_pool.contains(&fund_alice));
+        assert!(bc.transactions_pool.contains(&from_alice));
+
+        // Every cheaper transaction is needed by another one
+        assert_eq!(
+            bc.add_to_pool(transfer("satoshi", "bob", 1, 2, 3)),
+            Err("Transaction pool is full.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_add_to_pool_pending_state() {
+        let (mut bc, keypairs) = bootstrap_chain(&[
+            ("satoshi", 100.into()),
+            ("alice", 0.into()),
+            ("bob", 0.into()),
+        ]);
+        bc.max_pool_size = Some(3);
+        let network_id = bc.network_id().to_string();
+        let transfer = |from: &str, to: &str, amount: u128, fee: u128, nonce| {
+            TransactionBuilder::new(TransactionData::Transfer {
+                to: to.to_string(),
+                amount: amount.into(),
+                fee: fee.into(),
+            })
+            .from(from.to_string())
+            .nonce(nonce)
+            .sign(&keypairs[from], &network_id)
+        };
+        let set_name = TransactionBuilder::new(TransactionData::SetMetadata {
+            key: "name".to_string(),
+            value: "Alice".to_string(),
+        })
+        .from("alice".to_string())
+        .sign(&keypairs["alice"], &network_id);
+
+        bc.add_to_pool(transfer("satoshi", "alice", 10, 1, 0))
+            .unwrap();
+        bc.add_to_pool(transfer("alice", "bob", 4, 2, 0)).unwrap();
+        bc.add_to_pool(set_name.clone()).unwrap();
+        // Evicts the metadata change, keeping the balances the others left
+        assert_eq!(
+            bc.add_to_pool(transfer("satoshi", "bob", 5, 3, 1)),
+            Ok(Some(set_name))
+        );
+        assert_eq!(
+            bc.add_to_pool(transfer("bob", "alice", 1, 0, 0)),
+            Err("Transaction pool is full.".to_string())
+        );
+
+        let pending = bc.pending.as_ref().unwrap().accounts.clone();
+        let (rebuilt, _) =
+            bc.select_transactions(&bc.transactions_pool, usize::MAX, usize::MAX, None);
+        assert_eq!(pending, rebuilt.accounts);
+        assert_eq!(pending["alice"].balance, 4);
+        assert_eq!(pending["bob"].balance, 9);
+        assert!(pending["alice"].metadata.is_empty());
+    }
+
+    #[test]
+    fn test_add_to_pool_fails() {
+        let bc = &mut Blockchain::new();
+        assert!(bc
+            .add_to_pool(create_account_tx("alice".to_string()))
+            .is_ok());
+
+        assert_eq!(
+            bc.add_to_pool(create_account_tx("alice".to_string())),
+            Err("AccountId already exist: alice".to_string())
+        );
+        assert_eq!(bc.transactions_pool.len(), 1);
+        assert!(bc.accounts.is_empty());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let carol = create_account_tx("carol".to_string());
+        let build = |transactions: Vec<Transaction>| {
+            let mut bc = Blockchain::new();
+            append_block_with_tx(&mut bc, 1, transactions).unwrap();
+            append_block_with_tx(&mut bc, 2, vec![carol.clone()]).unwrap();
+            bc
+        };
+        let alice = create_account_tx("alice".to_string());
+        let bob = create_account_tx("bob".to_string());
+
+        let first = build(vec![alice.clone(), bob.clone()]);
+        let second = build(vec![alice.clone(), bob.clone()]);
+        assert_eq!(first.fingerprint(), second.fingerprint());
+        assert_eq!(first.fingerprint().len(), 64);
+
+        let reordered = build(vec![bob.clone(), alice.clone()]);
+        assert_ne!(first.fingerprint(), reordered.fingerprint());
+
+        let mut altered = build(vec![alice, bob]);
+        altered.blocks.iter_mut().last().unwrap().transactions[0] =
+            create_account_tx("dave".to_string());
+        assert_ne!(first.fingerprint(), altered.fingerprint());
+        assert_ne!(Blockchain::new().fingerprint(), first.fingerprint());
+    }
+
+    #[test]
+    fn test_accounts_by_balance() {
+        let bc = &mut Blockchain::new();
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mut genesis = vec![];
+        for (id, amount) in [("carol", 50), ("alice", 300), ("bob", 50), ("dave", 0)] {
+            genesis.push(Transaction::new(
+                TransactionData::CreateAccount(id.to_string(), keypair.public),
+                None,
+            ));
+            genesis.push(Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: id.to_string(),
+                    amount: amount.into(),
+                },
+                None,
+            ));
+        }
+        append_block_with_tx(bc, 1, genesis).unwrap();
+
+        let ranked = |limit| {
+            bc.accounts_by_balance(limit)
+                .into_iter()
+                .map(|(id, balance)| (id, balance.value()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ranked(3),
+            vec![
+                ("alice".to_string(), 300),
+                ("bob".to_string(), 50),
+                ("carol".to_string(), 50),
+            ]
+        );
+        assert_eq!(ranked(1), vec![("alice".to_string(), 300)]);
+        assert_eq!(ranked(10).len(), 4);
+        assert!(ranked(0).is_empty());
+    }
+
+    #[test]
+    fn test_can_afford() {
+        let bc = &mut Blockchain::new();
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mut genesis = vec![];
+        for (id, amount) in [("alice", 100.into()), ("whale", Balance::MAX)] {
+            genesis.push(Transaction::new(
+                TransactionData::CreateAccount(id.to_string(), keypair.public),
+                None,
+            ));
+            genesis.push(Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: id.to_string(),
+                    amount,
+                },
+                None,
+            ));
+        }
+        append_block_with_tx(bc, 1, genesis).unwrap();
+        let alice = "alice".to_string();
+        let whale = "whale".to_string();
+
+        assert_eq!(bc.balance_of(&alice), Some(100.into()));
+        assert!(bc.can_afford(&alice, 90.into(), 10.into()));
+        assert!(!bc.can_afford(&alice, 91.into(), 10.into()));
+        assert!(!bc.can_afford(&"bob".to_string(), 0.into(), 0.into()));
+
+        assert!(bc.can_afford(&whale, Balance::MAX, 0.into()));
+        // The sum overflows, it must not wrap around to a small amount
+        assert!(!bc.can_afford(&whale, Balance::MAX, 1.into()));
+        assert!(!bc.can_afford(&alice, Balance::MAX, 2.into()));
+    }
+
+    #[test]
+    fn test_load_from_file_expecting_genesis() {
+        let bc = &mut Blockchain::new();
+        append_block(bc, 1);
+        append_block(bc, 2);
+        let path = std::env::temp_dir().join(format!(
+            "blockchain-test-{}.json",
+            generate_random_account()
+        ));
+        bc.save_to_file(&path).unwrap();
+
+        let genesis = bc.chain_id().unwrap();
+        let loaded: Blockchain =
+            Blockchain::load_from_file_expecting_genesis(&path, genesis.clone()).unwrap();
+        assert_eq!(loaded.get_last_block_hash(), bc.get_last_block_hash());
+        assert_eq!(
+            Blockchain::<Blake2sHasher>::load_from_file_expecting_genesis(
+                &path,
+                "other".to_string()
+            )
+            .unwrap_err(),
+            format!("Genesis mismatch: expected other, found {}", genesis)
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(Blockchain::<Blake2sHasher>::load_from_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_import_blocks() {
+        let bc = &mut Blockchain::new();
+        for nonce in 1..=100 {
+            append_block(bc, nonce);
+        }
+        let mut stream = Vec::new();
+        bc.export_blocks(&mut stream).unwrap();
+
+        let imported: Blockchain = Blockchain::import_blocks(stream.as_slice()).unwrap();
+        assert_eq!(imported.height(), 100);
+        assert_eq!(imported.get_last_block_hash(), bc.get_last_block_hash());
+        assert_eq!(imported.state_hash(), bc.state_hash());
+
+        // Flip a byte of the 50th block's nonce, right after its length prefix
+        let offset = bc
+            .blocks
+            .iter_forward()
+            .take(49)
+            .map(|block| 4 + block.size_bytes())
+            .sum::<usize>();
+        let mut corrupted = stream.clone();
+        corrupted[offset + 4] ^= 1;
+        let error = Blockchain::<Blake2sHasher>::import_blocks(corrupted.as_slice()).unwrap_err();
+        assert_eq!(error, "Block 50: Block has invalid hash");
+
+        let truncated = &stream[..stream.len() - 1];
+        let error = Blockchain::<Blake2sHasher>::import_blocks(truncated).unwrap_err();
+        assert!(error.starts_with("Block 100: "));
+
+        let mut oversized = stream[..offset].to_vec();
+        oversized.extend(u32::MAX.to_be_bytes());
+        assert_eq!(
+            Blockchain::<Blake2sHasher>::import_blocks(oversized.as_slice()).unwrap_err(),
+            format!(
+                "Block 50 is too large: {} > {} bytes",
+                u32::MAX,
+                MAX_BLOCK_BYTES
+            )
+        );
+    }
+
+    #[test]
+    fn test_export_state() {
+        let bc = &mut Blockchain::new();
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        append_block_with_tx(
+            bc,
+            1,
+            vec![
+                Transaction::new(
+                    TransactionData::CreateAccount("satoshi".to_string(), keypair.public),
+                    None,
+                ),
+                Transaction::new(
+                    TransactionData::MintInitialSupply {
+                        to: "satoshi".to_string(),
+                        amount: 1000.into(),
+                    },
+                    None,
+                ),
+            ],
+        )
+        .unwrap();
+        append_block(bc, 2);
+        bc.accounts.get_mut("satoshi").unwrap().metadata.extend([
+            ("a".to_string(), "1".to_string()),
+            ("b".to_string(), "2".to_string()),
+        ]);
+
+        let export = bc.export_state();
+        let json = serde_json::to_string(&export).unwrap();
+        let mut imported = Blockchain::new();
+        imported
+            .import_state(serde_json::from_str(&json).unwrap())
+            .unwrap();
+
+        assert!(imported.blocks.is_empty());
+        assert_eq!(imported.accounts.len(), 2);
+        assert_eq!(imported.state_hash(), bc.state_hash());
+        for (id, account) in bc.accounts.iter() {
+            assert_eq!(imported.accounts[id].balance, account.balance);
+        }
+
+        let mut tampered = bc.export_state();
+        tampered.accounts.get_mut("satoshi").unwrap().balance = 1.into();
+        let mut state = Blockchain::new();
+        assert!(state
+            .import_state(tampered)
+            .unwrap_err()
+            .starts_with("State hash mismatch"));
+        assert!(state.accounts.is_empty());
+    }
+
+    #[test]
+    fn test_simulate() {
+        let bc = &mut Blockchain::new();
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        assert!(append_block_with_tx(
+            bc,
+            1,
+            vec![
+                Transaction::new(
+                    TransactionData::CreateAccount("satoshi".to_string(), keypair.public),
+                    None,
+                ),
+                create_account_tx("alice".to_string()),
+                Transaction::new(
+                    TransactionData::MintInitialSupply {
+                        to: "satoshi".to_string(),
+                        amount: 100.into(),
+                    },
+                    None,
+                ),
+            ],
+        )
+        .is_ok());
+
+        let mut overdraw = Transaction::new(
+            TransactionData::Transfer {
+                to: "alice".to_string(),
+                amount: 101.into(),
+                fee: 0.into(),
+            },
+            Some("satoshi".to_string()),
+        );
+        overdraw.add_signature(sign_hash(&keypair, bc.network_id(), &overdraw.hash()));
+        assert_eq!(
+            bc.simulate(&overdraw),
+            Err("Insufficient balance".to_string())
+        );
+
+        let mut tx = Transaction::new(
+            TransactionData::Transfer {
+                to: "alice".to_string(),
+                amount: 30.into(),
+                fee: 5.into(),
+            },
+            Some("satoshi".to_string()),
+        );
+        tx.add_signature(sign_hash(&keypair, bc.network_id(), &tx.hash()));
+        let result = bc.simulate(&tx).unwrap();
+        assert_eq!(result.balances.len(), 2);
+        assert_eq!(result.balances[&"satoshi".to_string()], 65);
+        assert_eq!(result.balances[&"alice".to_string()], 30);
+        assert_eq!(result.fee, 5);
+
+        assert_eq!(
+            bc.get_account_by_id(&"satoshi".to_string())
+                .unwrap()
+                .balance,
+            100
+        );
+        assert_eq!(
+            bc.get_account_by_id(&"alice".to_string()).unwrap().balance,
+            0
+        );
+    }
+
+    #[test]
+    fn test_prune() {
+        let bc = &mut Blockchain::new();
+        let satoshi = create_account_tx("satoshi".to_string());
+        let mint = Transaction::new(
+            TransactionData::MintInitialSupply {
+                to: "satoshi".to_string(),
+                amount: 100_000_000.into(),
+            },
+            None,
+        );
+        assert!(append_block_with_tx(bc, 1, vec![satoshi, mint]).is_ok());
+        for nonce in 2..=10 {
+            append_block(bc, nonce);
+        }
+        let last_block_hash = bc.get_last_block_hash();
+
+        bc.prune(3);
+
+        assert_eq!(bc.len(), 3);
+        assert_eq!(bc.get_last_block_hash(), last_block_hash);
+        assert_eq!(bc.accounts.len(), 10);
+        assert_eq!(
+            bc.get_account_by_id(&"satoshi".to_string())
+                .unwrap()
+                .balance,
+            100_000_000
+        );
+        assert!(bc.validate().is_ok());
+
+        append_block(bc, 11);
+        assert_eq!(bc.len(), 4);
+        assert!(bc.validate().is_ok());
+    }
+
+    #[test]
+    fn test_replay_state() {
+        let bc = &mut Blockchain::new();
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        append_block_with_tx(
+            bc,
+            1,
+            vec![
+                Transaction::new(
+                    TransactionData::CreateAccount("satoshi".to_string(), keypair.public),
+                    None,
+                ),
+                Transaction::new(
+                    TransactionData::MintInitialSupply {
+                        to: "satoshi".to_string(),
+                        amount: 100.into(),
+                    },
+                    None,
+                ),
+            ],
+        )
+        .unwrap();
+        let transfer = TransactionBuilder::new(TransactionData::Transfer {
+            to: "alice".to_string(),
+            amount: 30.into(),
+            fee: 0.into(),
+        })
+        .from("satoshi".to_string())
+        .sign(&keypair, bc.network_id());
+        append_block_with_tx(
+            bc,
+            2,
+            vec![create_account_tx("alice".to_string()), transfer],
+        )
+        .unwrap();
+
+        let balances = |bc: &Blockchain| {
+            bc.accounts
+                .iter()
+                .map(|(id, account)| (id.clone(), account.balance))
+                .collect::<HashMap<_, _>>()
+        };
+        let expected = balances(bc);
+
+        bc.accounts.clear();
+        assert!(bc.replay_state().is_ok());
+        assert_eq!(balances(bc), expected);
+        assert_eq!(bc.total_supply(), 100);
+
+        bc.prune(1);
+        assert_eq!(
+            bc.replay_state(),
+            Err("Can't replay a pruned chain.".to_string())
+        );
+        assert_eq!(balances(bc), expected);
+    }
+
+    #[test]
+    fn test_append_block_with_receipt() {
+        let bc = &mut Blockchain::new();
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let satoshi = "satoshi".to_string();
+        let alice = "alice".to_string();
+        let mut genesis = Block::new(None);
+        genesis.add_transaction(Transaction::new(
+            TransactionData::CreateAccount(satoshi.clone(), keypair.public),
+            None,
+        ));
+        genesis.add_transaction(Transaction::new(
+            TransactionData::MintInitialSupply {
+                to: satoshi.clone(),
+                amount: 1000.into(),
+            },
+            None,
+        ));
+        let receipt = bc.append_block_with_receipt(genesis).unwrap();
+        assert_eq!(receipt.block_hash, bc.get_last_block_hash().unwrap());
+        assert_eq!(receipt.transactions.len(), 2);
+        assert!(!receipt.transactions[0].is_coinbase);
+        assert!(receipt.transactions[1].is_coinbase);
+        assert_eq!(
+            receipt.transactions[1].changes,
+            vec![BalanceChange {
+                account_id: satoshi.clone(),
+                before: 0.into(),
+                after: 1000.into(),
+            }]
+        );
+
+        let mut block = Block::new(bc.get_last_block_hash());
+
+        block.set_timestamp(1);
+        block.add_transaction(create_account_tx(alice.clone()));
+        let transfer = TransactionBuilder::new(TransactionData::Transfer {
+            to: alice.clone(),
+            amount: 100.into(),
+            fee: 5.into(),
+        })
+        .from(satoshi.clone())
+        .sign(&keypair, bc.network_id());
+        block.add_transaction(transfer.clone());
+        set_state_root(bc, &mut block);
+        let receipt = bc.append_block_with_receipt(block).unwrap();
+
+        let transfer_receipt = &receipt.transactions[1];
+        assert_eq!(transfer_receipt.tx_hash, transfer.hash());
+        assert_eq!(transfer_receipt.fee, 5);
+        assert!(!transfer_receipt.is_coinbase);
+        assert_eq!(
+            transfer_receipt.changes,
+            vec![
+                BalanceChange {
+                    account_id: satoshi.clone(),
+                    before: 1000.into(),
+                    after: 895.into(),
+                },
+                BalanceChange {
+                    account_id: alice.clone(),
+                    before: 0.into(),
+                    after: 100.into(),
+                },
+            ]
+        );
+        assert_eq!(transfer_receipt.change_of(&alice).unwrap().after, 100);
+        assert_eq!(transfer_receipt.change_of(&"bob".to_string()), None);
+    }
+
+    #[test]
+    fn test_balance_at() {
+        let bc = &mut Blockchain::new();
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let alice = Keypair::generate(&mut rand::rngs::OsRng {});
+        append_block_with_tx(
+            bc,
+            1,
+            vec![
+                Transaction::new(
+                    TransactionData::CreateAccount("satoshi".to_string(), keypair.public),
+                    None,
+                ),
+                Transaction::new(
+                    TransactionData::CreateAccount("alice".to_string(), alice.public),
+                    None,
+                ),
+                Transaction::new(
+                    TransactionData::MintInitialSupply {
+                        to: "satoshi".to_string(),
+                        amount: 1000.into(),
+                    },
+                    None,
+                ),
+            ],
+        )
+        .unwrap();
+        let network_id = bc.network_id().to_string();
+        let transfer = |to: &str, amount, keypair: &Keypair, from: &str| {
+            TransactionBuilder::new(TransactionData::Transfer {
+                to: to.to_string(),
+                amount,
+                fee: 0.into(),
+            })
+            .from(from.to_string())
+            .sign(keypair, &network_id)
+        };
+        append_block_with_tx(
+            bc,
+            2,
+            vec![transfer("alice", 100.into(), &keypair, "satoshi")],
+        )
+        .unwrap();
+        append_block_with_tx(bc, 3, vec![transfer("satoshi", 30.into(), &alice, "alice")]).unwrap();
+
+        let alice_id = "alice".to_string();
+        assert_eq!(bc.balance_at(&alice_id, 1), Ok(0.into()));
+        assert_eq!(bc.balance_at(&alice_id, 2), Ok(100.into()));
+        assert_eq!(bc.balance_at(&alice_id, 3), Ok(70.into()));
+        assert_eq!(
+            bc.balance_at(&alice_id, 4),
+            Err("Height 4 is out of range".to_string())
+        );
+        assert_eq!(
+            bc.balance_at(&alice_id, 0),
+            Err("Height 0 is out of range".to_string())
+        );
+        assert_eq!(
+            bc.balance_at(&"bob".to_string(), 3),
+            Err("Account not found: bob".to_string())
+        );
+    }
+
+    #[test]
+    fn test_load_without_pruned() {
+        let bc = &mut Blockchain::new();
+        append_block(bc, 1);
+        let mut json = serde_json::to_value(&*bc).unwrap();
+        json.as_object_mut().unwrap().remove("pruned");
+
+        let loaded: Blockchain = serde_json::from_value(json).unwrap();
+        assert_eq!(loaded.height(), 1);
+        assert!(loaded.validate().is_ok());
+    }
+
+    #[test]
+    fn test_load_without_genesis_marker() {
+        let bc = &mut Blockchain::new();
+        append_block(bc, 1);
+        append_block(bc, 2);
+        let mut json = serde_json::to_value(&*bc).unwrap();
+        for block in json["blocks"].as_array_mut().unwrap() {
+            block.as_object_mut().unwrap().remove("is_genesis");
+        }
 
-        assert_eq!(
-            bc.validate(),
-            Err(String::from("Block 2 prev_hash doesn't match Block 1 hash"))
-        );
+        let loaded: Blockchain = serde_json::from_value(json).unwrap();
+        assert!(loaded.blocks.tail().unwrap().is_genesis());
+        assert!(!loaded.blocks.head().unwrap().is_genesis());
+        assert!(loaded.validate().is_ok());
     }
 
     #[test]
-    fn test_validate_prev_hash_none() {
+    fn test_prune_keeps_head() {
         let bc = &mut Blockchain::new();
-
         append_block(bc, 1);
         append_block(bc, 2);
-        let block = bc.blocks.iter_mut().next().unwrap();
-        block.prev_hash = None;
-        block.hash = Some(block.hash());
+        let last_block_hash = bc.get_last_block_hash();
 
-        assert_eq!(
-            bc.validate(),
-            Err(String::from("Block 2 doesn't have prev_hash"))
-        );
+        bc.prune(0);
+
+        assert_eq!(bc.len(), 1);
+        assert_eq!(bc.get_last_block_hash(), last_block_hash);
     }
 
     #[test]
@@ -224,6 +3844,8 @@ mod tests {
         assert!(bc.append_block(block).is_ok());
 
         let mut block = Block::new(bc.get_last_block_hash());
+
+        block.set_timestamp(1);
         block.set_nonce(2);
 
         assert_eq!(
@@ -241,8 +3863,10 @@ mod tests {
 
         let tx = create_account_tx("alice".to_string());
         let mut block = Block::new(bc.get_last_block_hash());
+        block.set_timestamp(1);
         block.set_nonce(2);
         block.add_transaction(tx);
+        set_state_root(bc, &mut block);
         assert!(bc.append_block(block).is_ok());
 
         let alice = bc.get_account_by_id(&"alice".to_string());
@@ -260,9 +3884,11 @@ mod tests {
         let tx1 = create_account_tx("alice".to_string());
         let tx2 = create_account_tx("alice".to_string());
         let mut block = Block::new(bc.get_last_block_hash());
+        block.set_timestamp(1);
         block.set_nonce(2);
         block.add_transaction(tx1);
         block.add_transaction(tx2);
+        set_state_root(bc, &mut block);
         assert_eq!(
             bc.append_block(block),
             Err("Error during executing transactions: AccountId already exist: alice".to_string())
@@ -272,6 +3898,238 @@ mod tests {
         assert!(alice.is_none());
     }
 
+    #[test]
+    fn test_rollback_transaction_without_sender() {
+        let bc = &mut Blockchain::new();
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mut transfer = Transaction::new(
+            TransactionData::Transfer {
+                to: "satoshi".to_string(),
+                amount: 0.into(),
+                fee: 0.into(),
+            },
+            None,
+        );
+        transfer.add_signature(sign_hash(&keypair, bc.network_id(), &transfer.hash()));
+        let result = append_block_with_tx(
+            bc,
+            1,
+            vec![
+                Transaction::new(
+                    TransactionData::CreateAccount("satoshi".to_string(), keypair.public),
+                    None,
+                ),
+                transfer,
+            ],
+        );
+
+        assert_eq!(
+            result.err(),
+            Some(format!(
+                "Error during executing transactions: {}",
+                MISSING_SENDER
+            ))
+        );
+        assert!(bc.is_empty());
+        assert!(bc.get_account_ids().is_empty());
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let payload = panic::catch_unwind(|| panic!("out of {}", "cheese")).unwrap_err();
+        assert_eq!(
+            panic_message(payload.as_ref()),
+            "Transaction panicked: out of cheese"
+        );
+        let payload = panic::catch_unwind(|| std::panic::panic_any(42)).unwrap_err();
+        assert_eq!(
+            panic_message(payload.as_ref()),
+            "Transaction panicked: unknown cause"
+        );
+    }
+
+    /// Keeps warnings of all tests, they run in parallel with a single global logger
+    struct CapturingLogger(std::sync::Mutex<Vec<(log::Level, String)>>);
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Warn
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                let message = record.args().to_string();
+                self.0.lock().unwrap().push((record.level(), message));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CapturingLogger = CapturingLogger(std::sync::Mutex::new(Vec::new()));
+
+    #[test]
+    fn test_rollback_logs_warning() {
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(log::LevelFilter::Warn);
+        }
+
+        let bc = &mut Blockchain::new();
+        append_block(bc, 1);
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.set_timestamp(1);
+        block.set_nonce(2);
+        block.add_transaction(create_account_tx("alice".to_string()));
+        block.add_transaction(create_account_tx("alice".to_string()));
+        set_state_root(bc, &mut block);
+        let hash = block.hash();
+        assert!(bc.append_block(block).is_err());
+
+        let logs = LOGGER.0.lock().unwrap();
+        assert!(logs.contains(&(
+            log::Level::Warn,
+            format!(
+                "Rolling back block {}: AccountId already exist: alice",
+                hash
+            )
+        )));
+    }
+
+    #[test]
+    fn test_rollback_partial_transfer() {
+        let bc = &mut Blockchain::new();
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        assert!(append_block_with_tx(
+            bc,
+            1,
+            vec![
+                Transaction::new(
+                    TransactionData::CreateAccount("satoshi".to_string(), keypair.public),
+                    None,
+                ),
+                Transaction::new(
+                    TransactionData::MintInitialSupply {
+                        to: "satoshi".to_string(),
+                        amount: 100.into(),
+                    },
+                    None,
+                ),
+            ],
+        )
+        .is_ok());
+
+        let mut to_bob = Transaction::new(
+            TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount: 30.into(),
+                fee: 0.into(),
+            },
+            Some("satoshi".to_string()),
+        );
+        to_bob.add_signature(sign_hash(&keypair, bc.network_id(), &to_bob.hash()));
+        // Sender is debited before the missing receiver is detected
+        let mut to_carol = Transaction::new(
+            TransactionData::Transfer {
+                to: "carol".to_string(),
+                amount: 20.into(),
+                fee: 0.into(),
+            },
+            Some("satoshi".to_string()),
+        );
+        to_carol.add_signature(sign_hash(&keypair, bc.network_id(), &to_carol.hash()));
+
+        assert_eq!(
+            append_block_with_tx(
+                bc,
+                2,
+                vec![create_account_tx("bob".to_string()), to_bob, to_carol],
+            )
+            .err()
+            .unwrap(),
+            "Error during executing transactions: Invalid receiver address.".to_string()
+        );
+
+        assert_eq!(bc.len(), 1);
+        assert_eq!(bc.get_account_ids(), vec!["satoshi".to_string()]);
+        assert_eq!(
+            bc.get_account_by_id(&"satoshi".to_string())
+                .unwrap()
+                .balance,
+            100
+        );
+    }
+
+    #[test]
+    fn test_multi_transaction_block() {
+        let bc = &mut Blockchain::new();
+        let satoshi = Keypair::generate(&mut rand::rngs::OsRng {});
+        let alice = Keypair::generate(&mut rand::rngs::OsRng {});
+        append_block_with_tx(
+            bc,
+            1,
+            vec![
+                Transaction::new(
+                    TransactionData::CreateAccount("satoshi".to_string(), satoshi.public),
+                    None,
+                ),
+                Transaction::new(
+                    TransactionData::MintInitialSupply {
+                        to: "satoshi".to_string(),
+                        amount: 100.into(),
+                    },
+                    None,
+                ),
+            ],
+        )
+        .unwrap();
+        let transfer = |from: &str, keypair: &Keypair, to: &str, amount: u128| {
+            TransactionBuilder::new(TransactionData::Transfer {
+                to: to.to_string(),
+                amount: amount.into(),
+                fee: 0.into(),
+            })
+            .from(from.to_string())
+            .sign(keypair, bc.network_id())
+        };
+
+        // Later transactions see the effects of earlier ones in the same block
+        let transactions = [
+            Transaction::new(
+                TransactionData::CreateAccount("alice".to_string(), alice.public),
+                None,
+            ),
+            transfer("satoshi", &satoshi, "alice", 40),
+            create_account_tx("bob".to_string()),
+            transfer("alice", &alice, "bob", 15),
+            transfer("alice", &alice, "bob", 30),
+        ];
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.set_timestamp(1);
+        for transaction in transactions.iter().cloned() {
+            block.add_transaction(transaction);
+        }
+        set_state_root(bc, &mut block);
+        let hash = block.hash();
+        assert_eq!(
+            bc.append_block(block.clone()),
+            Err("Error during executing transactions: Insufficient balance".to_string())
+        );
+        assert_eq!(bc.len(), 1);
+        assert_eq!(bc.get_account_ids(), vec!["satoshi".to_string()]);
+        assert_eq!(bc.balance_of(&"satoshi".to_string()), Some(100.into()));
+
+        // The rejected block is left untouched by the rollback
+        assert_eq!(block.hash(), hash);
+        assert_eq!(block.transactions_len(), transactions.len());
+
+        let appended = append_block_with_tx(bc, 2, transactions[..4].to_vec()).unwrap();
+        assert_eq!(appended.transactions_len(), 4);
+        assert_eq!(bc.balance_of(&"satoshi".to_string()), Some(60.into()));
+        assert_eq!(bc.balance_of(&"alice".to_string()), Some(25.into()));
+        assert_eq!(bc.balance_of(&"bob".to_string()), Some(15.into()));
+        assert_eq!(bc.validate(), Ok(()));
+    }
+
     #[test]
     fn test_initial_supply_fails() {
         let mut bc = Blockchain::new();
@@ -281,14 +4139,36 @@ mod tests {
         block.add_transaction(Transaction::new(
             TransactionData::MintInitialSupply {
                 to: "satoshi".to_string(),
-                amount: 100_000_000,
+                amount: 100_000_000.into(),
+            },
+            None,
+        ));
+        assert_eq!(
+            bc.append_block(block),
+            Err("Error during executing transactions: Account not found: satoshi".to_string())
+        );
+    }
+
+    #[test]
+    fn test_initial_supply_before_account_fails() {
+        let mut bc = Blockchain::new();
+
+        let mut block = Block::new(None);
+        block.set_nonce(1);
+        block.add_transaction(Transaction::new(
+            TransactionData::MintInitialSupply {
+                to: "satoshi".to_string(),
+                amount: 100_000_000.into(),
             },
             None,
         ));
+        block.add_transaction(create_account_tx("satoshi".to_string()));
         assert_eq!(
             bc.append_block(block),
-            Err("Error during executing transactions: Invalid account.".to_string())
+            Err("Error during executing transactions: Account not found: satoshi".to_string())
         );
+        assert!(bc.is_empty());
+        assert!(bc.accounts.is_empty());
     }
 
     #[test]
@@ -307,12 +4187,12 @@ mod tests {
         let mut tx = Transaction::new(
             TransactionData::MintInitialSupply {
                 to: "satoshi".to_string(),
-                amount: 100_000_000,
+                amount: 100_000_000.into(),
             },
             Some("satoshi".to_string()),
         );
         //TODO Task 2: Signature
-        tx.add_signature(keypair.sign(tx.hash().as_bytes()).to_bytes());
+        tx.add_signature(sign_hash(&keypair, bc.network_id(), &tx.hash()));
 
         assert_eq!(
             append_block_with_tx(bc, 2, vec![tx]).err().unwrap(),
@@ -320,6 +4200,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_initial_supply_minted_once() {
+        let mint = |amount| {
+            Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: "satoshi".to_string(),
+                    amount,
+                },
+                None,
+            )
+        };
+
+        let bc = &mut Blockchain::new();
+        assert_eq!(
+            append_block_with_tx(
+                bc,
+                1,
+                vec![
+                    create_account_tx("satoshi".to_string()),
+                    mint(100.into()),
+                    mint(50.into())
+                ]
+            )
+            .err()
+            .unwrap(),
+            "Error during executing transactions: Initial supply already minted: satoshi"
+                .to_string()
+        );
+
+        assert!(append_block_with_tx(
+            bc,
+            1,
+            vec![create_account_tx("satoshi".to_string()), mint(100.into())]
+        )
+        .is_ok());
+        assert_eq!(bc.accounts["satoshi"].balance, 100);
+    }
+
     #[test]
     fn test_initial_supply_works() {
         let mut bc = Blockchain::new();
@@ -330,7 +4248,7 @@ mod tests {
         block.add_transaction(Transaction::new(
             TransactionData::MintInitialSupply {
                 to: "satoshi".to_string(),
-                amount: 100_000_000,
+                amount: 100_000_000.into(),
             },
             None,
         ));
@@ -341,6 +4259,92 @@ mod tests {
         assert_eq!(account.unwrap().balance, 100_000_000);
     }
 
+    #[test]
+    fn test_max_supply() {
+        let mint = |to: &str, amount| {
+            Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: to.to_string(),
+                    amount,
+                },
+                None,
+            )
+        };
+
+        let bc = &mut Blockchain::new();
+        bc.max_supply = Some(100.into());
+        let result = append_block_with_tx(
+            bc,
+            1,
+            vec![
+                create_account_tx("satoshi".to_string()),
+                create_account_tx("alice".to_string()),
+                mint("satoshi", 60.into()),
+                mint("alice", 40.into()),
+            ],
+        );
+        assert!(result.is_ok());
+        assert_eq!(bc.total_supply(), 100);
+
+        let bc = &mut Blockchain::new();
+        bc.max_supply = Some(100.into());
+        let result = append_block_with_tx(
+            bc,
+            1,
+            vec![
+                create_account_tx("satoshi".to_string()),
+                create_account_tx("alice".to_string()),
+                mint("satoshi", 60.into()),
+                mint("alice", 41.into()),
+            ],
+        );
+        assert_eq!(
+            result.err().unwrap(),
+            "Error during executing transactions: Supply cap exceeded: 60 + 41 > 100".to_string()
+        );
+        assert_eq!(bc.total_supply(), 0);
+    }
+
+    fn format_balances<T: WorldStateRead + ?Sized>(state: &T) -> Vec<String> {
+        let mut ids = state.get_account_ids();
+        ids.sort();
+        ids.iter()
+            .filter_map(|id| {
+                Some(format!(
+                    "{}: {}",
+                    id,
+                    state.get_account_by_id(id)?.balance()
+                ))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_read_only_state() {
+        let mut bc = Blockchain::new();
+        append_block_with_tx(
+            &mut bc,
+            1,
+            vec![
+                create_account_tx("satoshi".to_string()),
+                create_account_tx("alice".to_string()),
+                Transaction::new(
+                    TransactionData::MintInitialSupply {
+                        to: "satoshi".to_string(),
+                        amount: 100.into(),
+                    },
+                    None,
+                ),
+            ],
+        )
+        .unwrap();
+
+        let bc = &bc;
+        let expected = vec!["alice: 0".to_string(), "satoshi: 100".to_string()];
+        assert_eq!(format_balances(bc), expected);
+        assert_eq!(format_balances(bc as &dyn WorldStateRead), expected);
+    }
+
     #[test]
     fn test_transfer() {
         let bc = &mut Blockchain::new();
@@ -358,7 +4362,7 @@ mod tests {
                 Transaction::new(
                     TransactionData::MintInitialSupply {
                         to: "satoshi".to_string(),
-                        amount: 100_000_000,
+                        amount: 100_000_000.into(),
                     },
                     None,
                 )
@@ -369,11 +4373,12 @@ mod tests {
         let mut tx = Transaction::new(
             TransactionData::Transfer {
                 to: "alice".to_string(),
-                amount: 10,
+                amount: 10.into(),
+                fee: 0.into(),
             },
             Some("satoshi".to_string()),
         );
-        tx.add_signature(keypair.sign(tx.hash().as_bytes()).to_bytes());
+        tx.add_signature(sign_hash(&keypair, bc.network_id(), &tx.hash()));
 
         assert!(
             append_block_with_tx(bc, 2, vec![create_account_tx("alice".to_string()), tx]).is_ok()
@@ -385,6 +4390,166 @@ mod tests {
         assert_eq!(satoshi.unwrap().balance, 99_999_990);
     }
 
+    #[test]
+    fn test_freeze_account() {
+        let bc = &mut Blockchain::new();
+        bc.admin = Some("satoshi".to_string());
+
+        let satoshi = Keypair::generate(&mut rand::rngs::OsRng {});
+        let alice = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mint = |to: &str| {
+            Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: to.to_string(),
+                    amount: 100.into(),
+                },
+                None,
+            )
+        };
+        assert!(append_block_with_tx(
+            bc,
+            1,
+            vec![
+                Transaction::new(
+                    TransactionData::CreateAccount("satoshi".to_string(), satoshi.public),
+                    None,
+                ),
+                Transaction::new(
+                    TransactionData::CreateAccount("alice".to_string(), alice.public),
+                    None,
+                ),
+                mint("satoshi"),
+                mint("alice"),
+            ]
+        )
+        .is_ok());
+
+        let network_id = bc.network_id().to_string();
+        let set_frozen = |frozen, nonce| {
+            TransactionBuilder::new(TransactionData::SetFrozen {
+                target: "alice".to_string(),
+                frozen,
+            })
+            .from("satoshi".to_string())
+            .nonce(nonce)
+            .sign(&satoshi, &network_id)
+        };
+        let transfer = |from: &str, to: &str, keypair: &Keypair, nonce| {
+            TransactionBuilder::new(TransactionData::Transfer {
+                to: to.to_string(),
+                amount: 10.into(),
+                fee: 0.into(),
+            })
+            .from(from.to_string())
+            .nonce(nonce)
+            .sign(keypair, &network_id)
+        };
+
+        assert!(append_block_with_tx(bc, 2, vec![set_frozen(true, 1)]).is_ok());
+        assert!(bc.accounts["alice"].is_frozen());
+        assert_eq!(
+            append_block_with_tx(bc, 3, vec![transfer("alice", "satoshi", &alice, 2)])
+                .err()
+                .unwrap(),
+            "Error during executing transactions: Account is frozen: alice".to_string()
+        );
+        assert!(
+            append_block_with_tx(bc, 3, vec![transfer("satoshi", "alice", &satoshi, 3)]).is_ok()
+        );
+
+        assert!(append_block_with_tx(bc, 4, vec![set_frozen(false, 4)]).is_ok());
+        assert!(append_block_with_tx(bc, 5, vec![transfer("alice", "satoshi", &alice, 5)]).is_ok());
+        assert_eq!(bc.accounts["alice"].balance, 100);
+        assert_eq!(bc.accounts["satoshi"].balance, 100);
+    }
+
+    #[test]
+    fn test_rotate_key() {
+        let bc = &mut Blockchain::new();
+        let old_key = Keypair::generate(&mut rand::rngs::OsRng {});
+        let new_key = Keypair::generate(&mut rand::rngs::OsRng {});
+        append_block_with_tx(
+            bc,
+            1,
+            vec![
+                Transaction::new(
+                    TransactionData::CreateAccount("alice".to_string(), old_key.public),
+                    None,
+                ),
+                create_account_tx("bob".to_string()),
+                Transaction::new(
+                    TransactionData::MintInitialSupply {
+                        to: "alice".to_string(),
+                        amount: 100.into(),
+                    },
+                    None,
+                ),
+            ],
+        )
+        .unwrap();
+
+        let rotate = TransactionBuilder::new(TransactionData::RotateKey {
+            new_public_key: new_key.public,
+        })
+        .from("alice".to_string())
+        .sign(&old_key, bc.network_id());
+        assert!(append_block_with_tx(bc, 2, vec![rotate]).is_ok());
+
+        let network_id = bc.network_id().to_string();
+        let transfer = |keypair: &Keypair, nonce| {
+            TransactionBuilder::new(TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount: 10.into(),
+                fee: 0.into(),
+            })
+            .from("alice".to_string())
+            .nonce(nonce)
+            .sign(keypair, &network_id)
+        };
+        assert_eq!(
+            append_block_with_tx(bc, 3, vec![transfer(&old_key, 1)])
+                .err()
+                .unwrap(),
+            "Error during executing transactions: Invalid signature.".to_string()
+        );
+        assert!(append_block_with_tx(bc, 3, vec![transfer(&new_key, 2)]).is_ok());
+        assert_eq!(bc.accounts["bob"].balance, 10);
+    }
+
+    #[test]
+    fn test_freeze_requires_admin() {
+        let bc = &mut Blockchain::new();
+        bc.admin = Some("satoshi".to_string());
+
+        let alice = Keypair::generate(&mut rand::rngs::OsRng {});
+        assert!(append_block_with_tx(
+            bc,
+            1,
+            vec![Transaction::new(
+                TransactionData::CreateAccount("alice".to_string(), alice.public),
+                None,
+            )]
+        )
+        .is_ok());
+
+        let tx = TransactionBuilder::new(TransactionData::SetFrozen {
+            target: "alice".to_string(),
+            frozen: true,
+        })
+        .from("alice".to_string())
+        .sign(&alice, bc.network_id());
+        assert_eq!(
+            bc.simulate(&tx).err().unwrap(),
+            "Only the admin account can freeze accounts.".to_string()
+        );
+        assert_eq!(
+            append_block_with_tx(bc, 2, vec![tx]).err().unwrap(),
+            "Error during executing transactions: Only the admin account can freeze accounts."
+                .to_string()
+        );
+        assert!(!bc.accounts["alice"].is_frozen());
+    }
+
     #[test]
     fn test_transfer_fails() {
         let bc = &mut Blockchain::new();
@@ -403,7 +4568,7 @@ mod tests {
                 Transaction::new(
                     TransactionData::MintInitialSupply {
                         to: "satoshi".to_string(),
-                        amount: 100_000_000,
+                        amount: 100_000_000.into(),
                     },
                     None,
                 ),
@@ -414,11 +4579,12 @@ mod tests {
         let mut tx = Transaction::new(
             TransactionData::Transfer {
                 to: "alice".to_string(),
-                amount: 100_000_001,
+                amount: 100_000_001.into(),
+                fee: 0.into(),
             },
             Some("satoshi".to_string()),
         );
-        tx.add_signature(keypair.sign(tx.hash().as_bytes()).to_bytes());
+        tx.add_signature(sign_hash(&keypair, bc.network_id(), &tx.hash()));
         assert_eq!(
             append_block_with_tx(bc, 2, vec![create_account_tx("alice".to_string()), tx],)
                 .err()
@@ -429,11 +4595,12 @@ mod tests {
         let mut tx = Transaction::new(
             TransactionData::Transfer {
                 to: "invalid_address".to_string(),
-                amount: 10,
+                amount: 10.into(),
+                fee: 0.into(),
             },
             Some("satoshi".to_string()),
         );
-        tx.add_signature(keypair.sign(tx.hash().as_bytes()).to_bytes());
+        tx.add_signature(sign_hash(&keypair, bc.network_id(), &tx.hash()));
         assert_eq!(
             append_block_with_tx(bc, 2, vec![create_account_tx("alice".to_string()), tx],)
                 .err()
@@ -444,11 +4611,12 @@ mod tests {
         let mut tx = Transaction::new(
             TransactionData::Transfer {
                 to: "alice".to_string(),
-                amount: 10,
+                amount: 10.into(),
+                fee: 0.into(),
             },
             Some("invalid_address".to_string()),
         );
-        tx.add_signature(keypair.sign(tx.hash().as_bytes()).to_bytes());
+        tx.add_signature(sign_hash(&keypair, bc.network_id(), &tx.hash()));
         assert_eq!(
             append_block_with_tx(bc, 2, vec![create_account_tx("alice".to_string()), tx])
                 .err()
@@ -476,7 +4644,7 @@ mod tests {
                 Transaction::new(
                     TransactionData::MintInitialSupply {
                         to: "satoshi".to_string(),
-                        amount: 100_000_000,
+                        amount: 100_000_000.into(),
                     },
                     Some("satoshi".to_string()),
                 ),
@@ -487,11 +4655,12 @@ mod tests {
         let mut tx = Transaction::new(
             TransactionData::Transfer {
                 to: "alice".to_string(),
-                amount: 100,
+                amount: 100.into(),
+                fee: 0.into(),
             },
             Some("satoshi".to_string()),
         );
-        tx.add_signature(keypair.sign(tx.hash().as_bytes()).to_bytes());
+        tx.add_signature(sign_hash(&keypair, bc.network_id(), &tx.hash()));
 
         assert!(
             append_block_with_tx(bc, 2, vec![create_account_tx("alice".to_string()), tx]).is_ok()
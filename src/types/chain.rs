@@ -1,82 +1,103 @@
-#[derive(Debug, Default, Clone)]
-pub struct Node<T> {
-    data: T,
-    prev: Option<Box<Node<T>>>,
+use std::collections::vec_deque::{self, VecDeque};
+use std::iter::Rev;
+
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+/// Append-only list that's walked from the newest item by default. Items are
+/// stored oldest first, so both directions take O(1) per step.
+#[derive(Debug, Clone)]
+pub struct Chain<T> {
+    items: VecDeque<T>,
 }
 
-impl<T> Node<T> {
-    pub fn new(data: T, prev: Option<Box<Node<T>>>) -> Self {
-        Self { data, prev }
+impl<T> Default for Chain<T> {
+    fn default() -> Self {
+        Self {
+            items: VecDeque::new(),
+        }
     }
 }
 
-#[derive(Debug, Default, Clone)]
-pub struct Chain<T: Default> {
-    head: Option<Box<Node<T>>>,
-    len: usize,
-}
+pub type ChainIter<'a, T> = Rev<vec_deque::Iter<'a, T>>;
+pub type ChainIterMut<'a, T> = Rev<vec_deque::IterMut<'a, T>>;
 
-impl<T: Default> Chain<T> {
+impl<T> Chain<T> {
     pub fn new() -> Self {
         Default::default()
     }
     pub fn append(&mut self, item: T) {
-        let head = self.head.take();
-        let node = Box::new(Node::new(item, head));
-
-        self.head = Some(node);
-        self.len += 1;
+        self.items.push_back(item);
     }
 
     pub fn head(&self) -> Option<&T> {
-        match &self.head {
-            None => None,
-            Some(head) => Some(&head.data),
-        }
+        self.items.back()
+    }
+
+    pub fn tail(&self) -> Option<&T> {
+        self.items.front()
+    }
+
+    pub fn pop_tail(&mut self) -> Option<T> {
+        self.items.pop_front()
     }
 
     pub fn len(&self) -> usize {
-        self.len
+        self.items.len()
     }
 
-    pub fn iter(&self) -> ChainIter<T> {
-        ChainIter {
-            next: self.head.as_deref(),
-        }
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
     }
 
-    pub fn iter_mut(&mut self) -> ChainIterMut<T> {
-        ChainIterMut {
-            next: self.head.as_deref_mut(),
-        }
+    /// Newest item matching the predicate
+    pub fn find<F: Fn(&T) -> bool>(&self, pred: F) -> Option<&T> {
+        self.iter().find(|item| pred(item))
     }
-}
 
-pub struct ChainIter<'a, T> {
-    next: Option<&'a Node<T>>,
-}
-pub struct ChainIterMut<'a, T> {
-    next: Option<&'a mut Node<T>>,
-}
+    pub fn contains<F: Fn(&T) -> bool>(&self, pred: F) -> bool {
+        self.find(pred).is_some()
+    }
 
-impl<'a, T> Iterator for ChainIter<'a, T> {
-    type Item = &'a T;
+    /// Folds items oldest first, unlike `iter` which goes from the head
+    pub fn fold_chronological<B, F>(&self, init: B, f: F) -> B
+    where
+        F: FnMut(B, &T) -> B,
+    {
+        self.iter_forward().fold(init, f)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.next.take().map(|node| {
-            self.next = node.prev.as_deref();
-            &node.data
-        })
+    /// Items from the head, newest first
+    pub fn iter(&self) -> ChainIter<'_, T> {
+        self.items.iter().rev()
+    }
+
+    pub fn iter_mut(&mut self) -> ChainIterMut<'_, T> {
+        self.items.iter_mut().rev()
+    }
+
+    /// Items from the tail, oldest first
+    pub fn iter_forward(&self) -> vec_deque::Iter<'_, T> {
+        self.items.iter()
+    }
+
+    /// Items oldest first
+    pub fn into_vec(self) -> Vec<T> {
+        self.items.into()
     }
 }
 
-impl<'a, T> Iterator for ChainIterMut<'a, T> {
-    type Item = &'a mut T;
+/// Chain is serialized as a plain sequence, oldest item first
+impl<T: Serialize> Serialize for Chain<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter_forward())
+    }
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.next.take().map(|node| {
-            self.next = node.prev.as_deref_mut();
-            &mut node.data
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Chain<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Chain {
+            items: VecDeque::deserialize(deserializer)?,
         })
     }
 }
@@ -99,4 +120,131 @@ mod tests {
         assert_eq!(chain.head(), Some(&10));
         assert_eq!(chain.len(), 3);
     }
+
+    #[test]
+    fn test_chain_tail() {
+        let mut chain = Chain::<u32>::new();
+        assert_eq!(chain.tail(), None);
+
+        chain.append(1);
+        chain.append(2);
+
+        assert_eq!(chain.tail(), Some(&1));
+        assert_eq!(chain.head(), Some(&2));
+    }
+
+    #[test]
+    fn test_pop_tail_empty() {
+        let mut chain = Chain::<u32>::new();
+
+        assert_eq!(chain.pop_tail(), None);
+        assert_eq!(chain.len(), 0);
+        assert!(chain.is_empty());
+    }
+
+    #[test]
+    fn test_pop_tail_single() {
+        let mut chain = Chain::<u32>::new();
+        chain.append(1);
+
+        assert!(!chain.is_empty());
+        assert_eq!(chain.pop_tail(), Some(1));
+        assert_eq!(chain.len(), 0);
+        assert!(chain.is_empty());
+        assert_eq!(chain.head(), None);
+        assert_eq!(chain.tail(), None);
+    }
+
+    #[test]
+    fn test_pop_tail() {
+        let mut chain = Chain::<u32>::new();
+        chain.append(1);
+        chain.append(2);
+        chain.append(10);
+
+        assert_eq!(chain.pop_tail(), Some(1));
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain.tail(), Some(&2));
+        assert_eq!(chain.head(), Some(&10));
+        assert_eq!(chain.iter().collect::<Vec<_>>(), vec![&10, &2]);
+    }
+
+    #[test]
+    fn test_iter_forward() {
+        let mut chain = Chain::<u32>::new();
+        assert_eq!(chain.iter_forward().next(), None);
+
+        for item in [1, 2, 3, 4] {
+            chain.append(item);
+        }
+
+        assert_eq!(chain.iter().collect::<Vec<_>>(), vec![&4, &3, &2, &1]);
+        assert_eq!(
+            chain.iter_forward().collect::<Vec<_>>(),
+            vec![&1, &2, &3, &4]
+        );
+        chain.pop_tail();
+        assert_eq!(chain.iter_forward().collect::<Vec<_>>(), vec![&2, &3, &4]);
+    }
+
+    #[test]
+    fn test_into_vec() {
+        let mut chain = Chain::<u32>::new();
+        assert!(chain.clone().into_vec().is_empty());
+
+        chain.append(1);
+        chain.append(2);
+        chain.append(10);
+        chain.pop_tail();
+        assert_eq!(chain.into_vec(), vec![2, 10]);
+    }
+
+    #[test]
+    fn test_find() {
+        let mut chain = Chain::<u32>::new();
+        assert_eq!(chain.find(|_| true), None);
+        assert!(!chain.contains(|_| true));
+
+        chain.append(1);
+        chain.append(2);
+        chain.append(10);
+
+        assert_eq!(chain.find(|item| item % 2 == 0), Some(&10));
+        assert_eq!(chain.find(|item| *item == 1), Some(&1));
+        assert_eq!(chain.find(|item| *item > 10), None);
+        assert!(chain.contains(|item| *item == 2));
+        assert!(!chain.contains(|item| *item == 3));
+    }
+
+    #[test]
+    fn test_fold_chronological() {
+        let mut chain = Chain::<u32>::new();
+        assert_eq!(chain.fold_chronological(0, |sum, item| sum + item), 0);
+
+        chain.append(1);
+        chain.append(2);
+        chain.append(10);
+
+        let running_sums = chain.fold_chronological(Vec::new(), |mut sums, item| {
+            sums.push(sums.last().unwrap_or(&0) + item);
+            sums
+        });
+        assert_eq!(running_sums, vec![1, 3, 13]);
+    }
+
+    #[test]
+    fn test_chain_serde() {
+        let mut chain = Chain::<u32>::new();
+        chain.append(1);
+        chain.append(2);
+        chain.append(10);
+
+        let json = serde_json::to_string(&chain).unwrap();
+        assert_eq!(json, "[1,2,10]");
+
+        let chain: Chain<u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(chain.len(), 3);
+        assert_eq!(chain.head(), Some(&10));
+        assert_eq!(chain.tail(), Some(&1));
+    }
 }
@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::{Balance, MAX_BLOCK_BYTES, MAX_TXS_PER_BLOCK};
+
+/// Rules every node of a chain has to agree on. The default is what the chain
+/// used before the rules were configurable.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConsensusConfig {
+    /// Leading zero bits every block hash needs
+    pub difficulty: u32,
+    pub max_txs_per_block: usize,
+    pub max_block_bytes: usize,
+    /// Most transactions one sender may have in a block, unlimited if not set
+    pub max_txs_per_account: Option<usize>,
+    /// Smallest non-zero transfer amount, to keep out dust transfers
+    pub min_transfer: Balance,
+    /// Most blocks below the head a replacing chain may undo, unlimited if not set
+    pub max_reorg_depth: Option<usize>,
+}
+
+impl Default for ConsensusConfig {
+    fn default() -> Self {
+        Self {
+            difficulty: 0,
+            max_txs_per_block: MAX_TXS_PER_BLOCK,
+            max_block_bytes: MAX_BLOCK_BYTES,
+            max_txs_per_account: None,
+            min_transfer: Balance::ZERO,
+            max_reorg_depth: None,
+        }
+    }
+}
@@ -0,0 +1,10 @@
+use crate::types::Hash;
+
+/// Update sent to every `Blockchain::subscribe` receiver
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainEvent {
+    /// Hash of a block that became the head
+    BlockAppended(Hash),
+    /// Hash of a transaction accepted into the pool
+    TransactionPooled(Hash),
+}
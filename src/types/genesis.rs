@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+
+use crate::traits::Hasher;
+use crate::types::{Balance, Block, Error, TransactionData};
+
+/// Rules the genesis block of a chain has to follow. The default accepts any genesis.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenesisPolicy {
+    /// Genesis has to create at least one account
+    #[serde(default)]
+    pub require_account: bool,
+    /// Exact amount genesis has to mint in total, if declared
+    #[serde(default)]
+    pub total_mint: Option<Balance>,
+}
+
+impl GenesisPolicy {
+    pub fn check<H: Hasher>(&self, block: &Block<H>) -> Result<(), Error> {
+        let data = block.transactions.iter().map(|tx| tx.data());
+
+        if self.require_account
+            && !data.clone().any(|data| {
+                matches!(
+                    data,
+                    TransactionData::CreateAccount(..)
+                        | TransactionData::CreateMultisigAccount { .. }
+                )
+            })
+        {
+            return Err("Genesis has to create an account.".to_string());
+        }
+
+        if let Some(total_mint) = self.total_mint {
+            let minted = data.fold(Balance::ZERO, |minted, data| match data {
+                TransactionData::MintInitialSupply { amount, .. } => minted.saturating_add(*amount),
+                _ => minted,
+            });
+            if minted != total_mint {
+                return Err(format!(
+                    "Genesis mints {} instead of {}",
+                    minted, total_mint
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Transaction;
+    use crate::utils::{create_account_tx, create_mint_initial_supply_tx};
+
+    fn genesis(transactions: Vec<Transaction>) -> Block {
+        let mut block = Block::genesis();
+        for tx in transactions {
+            block.add_transaction(tx);
+        }
+        block
+    }
+
+    #[test]
+    fn test_default_policy() {
+        assert!(GenesisPolicy::default().check(&genesis(vec![])).is_ok());
+    }
+
+    #[test]
+    fn test_compliant_genesis() {
+        let policy = GenesisPolicy {
+            require_account: true,
+            total_mint: Some(150.into()),
+        };
+        let block = genesis(vec![
+            create_account_tx("satoshi".to_string()),
+            create_account_tx("alice".to_string()),
+            Transaction::new(
+                create_mint_initial_supply_tx("satoshi".to_string(), 100.into()),
+                None,
+            ),
+            Transaction::new(
+                create_mint_initial_supply_tx("alice".to_string(), 50.into()),
+                None,
+            ),
+        ]);
+
+        assert_eq!(policy.check(&block), Ok(()));
+    }
+
+    #[test]
+    fn test_genesis_minting_more_than_declared() {
+        let policy = GenesisPolicy {
+            require_account: true,
+            total_mint: Some(100.into()),
+        };
+        let block = genesis(vec![
+            create_account_tx("satoshi".to_string()),
+            Transaction::new(
+                create_mint_initial_supply_tx("satoshi".to_string(), 101.into()),
+                None,
+            ),
+        ]);
+
+        assert_eq!(
+            policy.check(&block),
+            Err("Genesis mints 101 instead of 100".to_string())
+        );
+    }
+
+    #[test]
+    fn test_genesis_without_accounts() {
+        let policy = GenesisPolicy {
+            require_account: true,
+            total_mint: None,
+        };
+
+        assert_eq!(
+            policy.check(&genesis(vec![])),
+            Err("Genesis has to create an account.".to_string())
+        );
+    }
+}
@@ -0,0 +1,41 @@
+use blake2::{Blake2s, Digest};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::traits::Hasher;
+use crate::types::Hash;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Blake2sHasher;
+
+impl Hasher for Blake2sHasher {
+    fn digest(&self, data: &[u8]) -> Hash {
+        hex::encode(Blake2s::digest(data))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Sha256Hasher;
+
+impl Hasher for Sha256Hasher {
+    fn digest(&self, data: &[u8]) -> Hash {
+        hex::encode(Sha256::digest(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digest() {
+        assert_eq!(
+            Blake2sHasher.digest(b"abc"),
+            "508c5e8c327c14e2e1a72ba34eeb452f37458b209ed63a294d999b4c86675982"
+        );
+        assert_eq!(
+            Sha256Hasher.digest(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -1,16 +1,40 @@
 mod account;
+mod balance;
 mod block;
 mod blockchain;
 mod chain;
+mod consensus;
+mod event;
+mod genesis;
+mod hasher;
+mod receipt;
+mod shared;
+mod snapshot;
 mod transaction;
+mod wallet;
 
-pub use self::blockchain::Blockchain;
-pub use account::{Account, AccountType};
-pub use block::Block;
-pub use transaction::{Transaction, TransactionData};
+pub use self::blockchain::{
+    Blockchain, SimulationResult, StateExport, MAX_BLOCK_BYTES, MAX_ORPHANS, MAX_TXS_PER_BLOCK,
+};
+pub use account::{
+    normalize_account_id, Account, AccountType, MAX_ACCOUNT_ID_LEN, MAX_METADATA_LEN,
+    MAX_METADATA_VALUE_LEN,
+};
+pub use balance::{Balance, BalanceOverflow};
+pub use block::{Block, BlockHeader, BlockVerifyError, MAX_MINER_NOTE_LEN, MINING_BATCH};
+pub use consensus::ConsensusConfig;
+pub use event::ChainEvent;
+pub use genesis::GenesisPolicy;
+pub use hasher::{Blake2sHasher, Sha256Hasher};
+pub use receipt::{BalanceChange, BlockReceipt, TransactionReceipt};
+pub use shared::SharedBlockchain;
+pub use snapshot::{Journaled, StateSnapshot};
+pub use transaction::{
+    Transaction, TransactionBuilder, TransactionData, MAX_MESSAGE_LEN, MISSING_SENDER,
+};
+pub use wallet::Wallet;
 
 pub type AccountId = String;
-pub type Balance = u128;
 /// Millist sinse unix epoch
 pub type Timestamp = u128;
 pub type Hash = String;
@@ -0,0 +1,37 @@
+use crate::types::{AccountId, Balance, Hash};
+
+/// Balance of an account before and after a transaction. Accounts created by
+/// the transaction start from zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceChange {
+    pub account_id: AccountId,
+    pub before: Balance,
+    pub after: Balance,
+}
+
+/// Effects of an executed transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionReceipt {
+    pub tx_hash: Hash,
+    /// Every account the transaction touched, even if its balance stayed the same
+    pub changes: Vec<BalanceChange>,
+    /// Burned transfer fee or account creation fee
+    pub fee: Balance,
+    /// Whether the transaction minted new coins
+    pub is_coinbase: bool,
+}
+
+impl TransactionReceipt {
+    pub fn change_of(&self, account_id: &AccountId) -> Option<&BalanceChange> {
+        self.changes
+            .iter()
+            .find(|change| &change.account_id == account_id)
+    }
+}
+
+/// Receipts of all transactions of an appended block, in block order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockReceipt {
+    pub block_hash: Hash,
+    pub transactions: Vec<TransactionReceipt>,
+}
@@ -0,0 +1,128 @@
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::traits::Hasher;
+use crate::types::{
+    AccountId, Balance, Blake2sHasher, Block, Blockchain, Error, Hash, Transaction,
+};
+
+/// Blockchain shared between threads: any number of readers or a single writer.
+/// A panic while holding the lock doesn't make the chain unusable.
+#[derive(Debug, Default, Clone)]
+pub struct SharedBlockchain<H = Blake2sHasher> {
+    inner: Arc<RwLock<Blockchain<H>>>,
+}
+
+impl<H: Hasher> SharedBlockchain<H> {
+    pub fn new(blockchain: Blockchain<H>) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(blockchain)),
+        }
+    }
+
+    /// Locks for reading, for several queries that have to see the same state
+    pub fn read(&self) -> RwLockReadGuard<'_, Blockchain<H>> {
+        self.inner
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, Blockchain<H>> {
+        self.inner
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn balance_of(&self, id: &AccountId) -> Option<Balance> {
+        self.read().balance_of(id)
+    }
+
+    pub fn height(&self) -> usize {
+        self.read().height()
+    }
+
+    pub fn get_block_by_hash(&self, hash: &Hash) -> Option<Block<H>> {
+        self.read().get_block_by_hash(hash).cloned()
+    }
+
+    pub fn append_block(&self, block: Block<H>) -> Result<Hash, Error> {
+        self.write().append_block(block)
+    }
+
+    pub fn add_to_pool(
+        &self,
+        transaction: Transaction<H>,
+    ) -> Result<Option<Transaction<H>>, Error> {
+        self.write().add_to_pool(transaction)
+    }
+
+    pub fn mine_pending_transactions(&self) -> Result<(), Error> {
+        self.write().mine_pending_transactions()
+    }
+}
+
+impl<H: Hasher> From<Blockchain<H>> for SharedBlockchain<H> {
+    fn from(blockchain: Blockchain<H>) -> Self {
+        Self::new(blockchain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::traits::WorldStateRead;
+    use crate::utils::create_account_tx;
+
+    #[test]
+    fn test_readers_and_writer() {
+        let shared = SharedBlockchain::new(Blockchain::new());
+
+        let readers = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    let mut last_height = 0;
+                    while last_height < 20 {
+                        let bc = shared.read();
+                        // Every block is executed, so each one has created an account
+                        assert_eq!(bc.get_account_ids().len(), bc.len());
+                        assert!(bc.len() >= last_height);
+                        if let Some(hash) = bc.get_last_block_hash() {
+                            assert!(bc.get_block_by_hash(&hash).is_some());
+                        }
+                        last_height = bc.len();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let writer = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for i in 0..20 {
+                    let mut block = Block::new(shared.read().get_last_block_hash());
+                    block.set_timestamp(i);
+                    block.add_transaction(create_account_tx(format!("account{}", i)));
+                    block.set_state_root(shared.read().state_root_after(&block).unwrap());
+                    shared.append_block(block).unwrap();
+                }
+            })
+        };
+
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(shared.height(), 20);
+        assert_eq!(
+            shared.balance_of(&"account7".to_string()),
+            Some(Balance::ZERO)
+        );
+        assert_eq!(shared.balance_of(&"unknown".to_string()), None);
+
+        // Pruned blocks still count
+        shared.write().prune(5);
+        assert_eq!(shared.height(), 20);
+    }
+}
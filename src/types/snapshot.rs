@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use ed25519_dalek::PublicKey;
+
+use crate::traits::{WorldState, WorldStateRead};
+use crate::types::{Account, AccountId, AccountType, Balance};
+
+/// Journal of account before-images, so a failed block can be rolled back
+/// without cloning the whole accounts map.
+#[derive(Debug, Default, Clone)]
+pub struct StateSnapshot {
+    journal: Vec<(AccountId, Option<Account>)>,
+}
+
+impl StateSnapshot {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Records the account as it is now, unless it's already recorded
+    pub fn record<T: WorldStateRead>(&mut self, state: &T, account_id: &AccountId) {
+        if !self.journal.iter().any(|(id, _)| id == account_id) {
+            self.journal.push((
+                account_id.clone(),
+                state.get_account_by_id(account_id).cloned(),
+            ));
+        }
+    }
+
+    /// Recorded accounts with their before-images, in the order they were recorded
+    pub fn entries(&self) -> impl Iterator<Item = (&AccountId, Option<&Account>)> {
+        self.journal
+            .iter()
+            .map(|(account_id, account)| (account_id, account.as_ref()))
+    }
+
+    /// Puts recorded accounts back in reverse order, removing the ones that didn't exist
+    pub fn restore(self, accounts: &mut HashMap<AccountId, Account>) {
+        for (account_id, account) in self.journal.into_iter().rev() {
+            match account {
+                Some(account) => accounts.insert(account_id, account),
+                None => accounts.remove(&account_id),
+            };
+        }
+    }
+}
+
+/// State wrapper recording every account before it's written to, so the
+/// accounts a transaction really touched are known and can be rolled back
+pub struct Journaled<'a, T> {
+    state: &'a mut T,
+    snapshot: StateSnapshot,
+}
+
+impl<'a, T: WorldState> Journaled<'a, T> {
+    pub fn new(state: &'a mut T) -> Self {
+        Self {
+            state,
+            snapshot: StateSnapshot::new(),
+        }
+    }
+
+    pub fn into_snapshot(self) -> StateSnapshot {
+        self.snapshot
+    }
+}
+
+impl<T: WorldState> WorldStateRead for Journaled<'_, T> {
+    fn get_account_ids(&self) -> Vec<AccountId> {
+        self.state.get_account_ids()
+    }
+
+    fn get_account_by_id(&self, id: &AccountId) -> Option<&Account> {
+        self.state.get_account_by_id(id)
+    }
+
+    fn network_id(&self) -> &str {
+        self.state.network_id()
+    }
+
+    fn admin(&self) -> Option<&AccountId> {
+        self.state.admin()
+    }
+
+    fn requires_derived_account_ids(&self) -> bool {
+        self.state.requires_derived_account_ids()
+    }
+
+    fn min_transfer(&self) -> Balance {
+        self.state.min_transfer()
+    }
+
+    fn account_creation_fee(&self) -> Balance {
+        self.state.account_creation_fee()
+    }
+
+    fn max_supply(&self) -> Option<Balance> {
+        self.state.max_supply()
+    }
+
+    fn total_supply(&self) -> Balance {
+        self.state.total_supply()
+    }
+}
+
+impl<T: WorldState> WorldState for Journaled<'_, T> {
+    fn get_account_by_id_mut(&mut self, id: &AccountId) -> Option<&mut Account> {
+        self.snapshot.record(&*self.state, id);
+        self.state.get_account_by_id_mut(id)
+    }
+
+    fn create_account(
+        &mut self,
+        account_id: AccountId,
+        account_type: AccountType,
+        public_key: PublicKey,
+    ) -> Result<(), String> {
+        self.snapshot.record(&*self.state, &account_id);
+        self.state
+            .create_account(account_id, account_type, public_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::Keypair;
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::traits::WorldState;
+    use crate::types::{AccountType, Blockchain};
+
+    #[test]
+    fn test_restore() {
+        let mut bc = Blockchain::new();
+        let public_key = Keypair::generate(&mut OsRng {}).public;
+        bc.create_account("alice".to_string(), AccountType::User, public_key)
+            .unwrap();
+
+        let mut snapshot = StateSnapshot::new();
+        snapshot.record(&bc, &"alice".to_string());
+        snapshot.record(&bc, &"bob".to_string());
+        bc.get_account_by_id_mut(&"alice".to_string())
+            .unwrap()
+            .balance = 10.into();
+        snapshot.record(&bc, &"alice".to_string());
+        bc.create_account("bob".to_string(), AccountType::User, public_key)
+            .unwrap();
+
+        snapshot.restore(&mut bc.accounts);
+
+        assert_eq!(bc.get_account_ids(), vec!["alice".to_string()]);
+        assert_eq!(
+            bc.get_account_by_id(&"alice".to_string()).unwrap().balance,
+            0
+        );
+    }
+    #[test]
+    fn test_journaled() {
+        let mut bc = Blockchain::new();
+        let public_key = Keypair::generate(&mut OsRng {}).public;
+        bc.create_account("alice".to_string(), AccountType::User, public_key)
+            .unwrap();
+
+        let mut journal = Journaled::new(&mut bc);
+        journal
+            .create_account("bob".to_string(), AccountType::User, public_key)
+            .unwrap();
+        journal
+            .get_account_by_id_mut(&"alice".to_string())
+            .unwrap()
+            .balance = 10.into();
+        assert!(journal.get_account_by_id(&"carol".to_string()).is_none());
+        let snapshot = journal.into_snapshot();
+
+        assert_eq!(
+            snapshot
+                .entries()
+                .map(|(id, account)| (id.as_str(), account.map(|account| account.balance)))
+                .collect::<Vec<_>>(),
+            vec![("bob", None), ("alice", Some(0.into()))]
+        );
+        snapshot.restore(&mut bc.accounts);
+        assert_eq!(bc.get_account_ids(), vec!["alice".to_string()]);
+        assert_eq!(bc.balance_of(&"alice".to_string()), Some(0.into()));
+    }
+}
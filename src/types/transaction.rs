@@ -1,43 +1,308 @@
-use blake2::{Blake2s, Digest};
-use ed25519_dalek::{PublicKey, Verifier};
+use std::fmt;
 
-use crate::traits::{Hashable, WorldState};
-use crate::types::{AccountId, AccountType, Balance, Error, Hash, Signature, Timestamp};
+use ed25519_dalek::{Keypair, PublicKey};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq)]
+use crate::traits::{Hashable, Hasher, Verifiable, WorldState, WorldStateRead};
+use crate::types::{
+    normalize_account_id, Account, AccountId, AccountType, Balance, BalanceChange, Blake2sHasher,
+    Error, Hash, Journaled, Signature, Timestamp, TransactionReceipt, MAX_METADATA_LEN,
+    MAX_METADATA_VALUE_LEN,
+};
+use crate::utils::{derive_account_id, short_hex, sign_hash, verify_hash};
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub enum TransactionData {
     CreateAccount(AccountId, PublicKey),
-    Transfer { to: AccountId, amount: Balance },
-    MintInitialSupply { to: AccountId, amount: Balance },
+    /// The fee is paid by the sender on top of the amount and burned
+    Transfer {
+        to: AccountId,
+        amount: Balance,
+        #[serde(default)]
+        fee: Balance,
+    },
+    MintInitialSupply {
+        to: AccountId,
+        amount: Balance,
+    },
+    /// Only the admin account of the chain may send it
+    SetFrozen {
+        target: AccountId,
+        frozen: bool,
+    },
+    /// Replaces the sender's key, signed by the key being replaced
+    RotateKey {
+        new_public_key: PublicKey,
+    },
+    /// Creates an account spendable with `threshold` of the signers' signatures
+    CreateMultisigAccount {
+        account_id: AccountId,
+        signers: Vec<PublicKey>,
+        threshold: usize,
+    },
+    /// Signed data anchored in a block, e.g. a document hash. Moves no funds.
+    Message {
+        payload: Vec<u8>,
+    },
+    /// Sets a metadata entry of the sender's account, an empty value removes it
+    SetMetadata {
+        key: String,
+        value: String,
+    },
 }
 
-#[derive(Debug, Clone)]
-pub struct Transaction {
+/// Debug output is part of the transaction hash, so public keys are printed as
+/// hex. `PublicKey`'s own Debug shows the curve point's internal coordinates,
+/// which differ between a generated key and the same key decoded from bytes.
+impl fmt::Debug for TransactionData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionData::CreateAccount(account_id, public_key) => f
+                .debug_tuple("CreateAccount")
+                .field(account_id)
+                .field(&hex::encode(public_key))
+                .finish(),
+            TransactionData::Transfer { to, amount, fee } => f
+                .debug_struct("Transfer")
+                .field("to", to)
+                .field("amount", amount)
+                .field("fee", fee)
+                .finish(),
+            TransactionData::MintInitialSupply { to, amount } => f
+                .debug_struct("MintInitialSupply")
+                .field("to", to)
+                .field("amount", amount)
+                .finish(),
+            TransactionData::SetFrozen { target, frozen } => f
+                .debug_struct("SetFrozen")
+                .field("target", target)
+                .field("frozen", frozen)
+                .finish(),
+            TransactionData::RotateKey { new_public_key } => f
+                .debug_struct("RotateKey")
+                .field("new_public_key", &hex::encode(new_public_key))
+                .finish(),
+            TransactionData::CreateMultisigAccount {
+                account_id,
+                signers,
+                threshold,
+            } => f
+                .debug_struct("CreateMultisigAccount")
+                .field("account_id", account_id)
+                .field(
+                    "signers",
+                    &signers.iter().map(hex::encode).collect::<Vec<_>>(),
+                )
+                .field("threshold", threshold)
+                .finish(),
+            TransactionData::Message { payload } => f
+                .debug_struct("Message")
+                .field("payload", &hex::encode(payload))
+                .finish(),
+            TransactionData::SetMetadata { key, value } => f
+                .debug_struct("SetMetadata")
+                .field("key", key)
+                .field("value", value)
+                .finish(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(serialize = "", deserialize = "H: Default"))]
+pub struct Transaction<H = Blake2sHasher> {
     nonce: u128,
     timestamp: Timestamp,
     pub(crate) data: TransactionData,
     pub(crate) from: Option<AccountId>,
-    signature: Option<Signature>,
+    /// Last timestamp of a block that may include the transaction
+    #[serde(default)]
+    valid_until: Option<Timestamp>,
+    /// Multisig accounts need several, every other sender a single one
+    #[serde(with = "signatures_serde")]
+    signatures: Vec<Signature>,
+    #[serde(skip)]
+    hasher: H,
+}
+
+/// Serde doesn't support arrays longer than 32, so signatures are stored as hex
+/// in human-readable formats and as raw bytes in binary ones
+mod signatures_serde {
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::types::Signature;
+
+    pub fn serialize<S: Serializer>(
+        signatures: &[Signature],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_seq(signatures.iter().map(hex::encode))
+        } else {
+            serializer.collect_seq(signatures.iter().map(|signature| &signature[..]))
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Signature>, D::Error> {
+        if !deserializer.is_human_readable() {
+            return Vec::<Vec<u8>>::deserialize(deserializer)?
+                .into_iter()
+                .map(|bytes| {
+                    Signature::try_from(bytes.as_slice()).map_err(|_| {
+                        D::Error::custom(format!("Signature must be 64 bytes, got {}", bytes.len()))
+                    })
+                })
+                .collect();
+        }
+
+        Vec::<String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|encoded| {
+                let mut signature = [0u8; 64];
+                hex::decode_to_slice(encoded, &mut signature).map_err(D::Error::custom)?;
+                Ok(signature)
+            })
+            .collect()
+    }
 }
 
-impl Hashable for Transaction {
+impl<H: Hasher> Hashable for Transaction<H> {
     fn hash(&self) -> Hash {
-        hex::encode(Blake2s::digest(
-            format!("{:?}", (self.nonce, self.timestamp, &self.data, &self.from)).as_bytes(),
-        ))
+        self.hasher.digest(
+            format!(
+                "{:?}",
+                (
+                    self.nonce,
+                    self.timestamp,
+                    &self.data,
+                    &self.from,
+                    self.valid_until
+                )
+            )
+            .as_bytes(),
+        )
+    }
+}
+
+/// One-line summary, keys and payloads are shortened
+impl<H: Hasher> fmt::Display for Transaction<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let from = self.from.as_deref().unwrap_or("unknown");
+        match &self.data {
+            TransactionData::CreateAccount(account_id, public_key) => write!(
+                f,
+                "CreateAccount {} with key {}",
+                account_id,
+                short_hex(&hex::encode(public_key))
+            ),
+            TransactionData::Transfer { to, amount, fee } => {
+                write!(
+                    f,
+                    "Transfer {} from {} to {}, fee {}",
+                    amount, from, to, fee
+                )
+            }
+            TransactionData::MintInitialSupply { to, amount } => {
+                write!(f, "MintInitialSupply {} to {}", amount, to)
+            }
+            TransactionData::SetFrozen { target, frozen } => {
+                write!(f, "SetFrozen {} to {} by {}", target, frozen, from)
+            }
+            TransactionData::RotateKey { new_public_key } => write!(
+                f,
+                "RotateKey of {} to {}",
+                from,
+                short_hex(&hex::encode(new_public_key))
+            ),
+            TransactionData::CreateMultisigAccount {
+                account_id,
+                signers,
+                threshold,
+            } => write!(
+                f,
+                "CreateMultisigAccount {} with {} of {} signers",
+                account_id,
+                threshold,
+                signers.len()
+            ),
+            TransactionData::Message { payload } => write!(
+                f,
+                "Message from {}: {}",
+                from,
+                short_hex(&hex::encode(payload))
+            ),
+            TransactionData::SetMetadata { key, value } => {
+                write!(f, "SetMetadata {} of {} to {:?}", key, from, value)
+            }
+        }
+    }
+}
+
+/// Transactions are equal when their hashes are, so the signature is ignored
+impl<H: Hasher> PartialEq for Transaction<H> {
+    fn eq(&self, other: &Self) -> bool {
+        Hashable::hash(self) == Hashable::hash(other)
+    }
+}
+
+impl<H: Hasher> Eq for Transaction<H> {}
+
+impl<H: Hasher> std::hash::Hash for Transaction<H> {
+    fn hash<S: std::hash::Hasher>(&self, state: &mut S) {
+        Hashable::hash(self).hash(state)
+    }
+}
+
+/// Checks only what can be checked without world state: signatures of an unfunded
+/// `CreateAccount` against the key it registers, and that any other signed
+/// transaction declares its sender. Signatures of other transactions are checked
+/// on execution.
+impl<H: Hasher> Verifiable for Transaction<H> {
+    fn verify(&self, network_id: &str) -> bool {
+        if self.signatures.is_empty() {
+            return true;
+        }
+        match &self.data {
+            TransactionData::CreateAccount(_, public_key) if self.from.is_none() => self
+                .signatures
+                .iter()
+                .all(|signature| self.is_signed_by(network_id, public_key, signature)),
+            _ => self.from.is_some(),
+        }
     }
 }
 
-/// State transition functions
+/// Largest `Message` payload in bytes
+pub const MAX_MESSAGE_LEN: usize = 256;
+
+/// Error of a transaction without `from` that needs a sender
+pub const MISSING_SENDER: &str = "Tx `from` is not defined.";
+
+// State transition functions
 
 fn create_account<T: WorldState>(
     state: &mut T,
     account_id: AccountId,
     public_key: PublicKey,
 ) -> Result<(), Error> {
+    let account_id = normalize_account_id(&account_id)?;
+    if state.account_exists(&account_id) {
+        return Err(format!("AccountId already exist: {}", account_id));
+    }
+    if state.requires_derived_account_ids() && account_id != derive_account_id(&public_key) {
+        return Err(format!(
+            "Account id {} isn't derived from its public key",
+            account_id
+        ));
+    }
     state.create_account(account_id, AccountType::User, public_key)
 }
 
+/// Transactions are executed in order, so the receiving account has to be created
+/// earlier in the genesis block than the mint. Every account can be minted to once.
 fn mint_initial_supply<T: WorldState>(
     state: &mut T,
     to: AccountId,
@@ -47,59 +312,234 @@ fn mint_initial_supply<T: WorldState>(
     if !is_genesis {
         return Err("Initial Supply can be minted only in genesis block".to_string());
     }
+    if let Some(max_supply) = state.max_supply() {
+        let total_supply = state.total_supply();
+        if total_supply.saturating_add(amount) > max_supply {
+            return Err(format!(
+                "Supply cap exceeded: {} + {} > {}",
+                total_supply, amount, max_supply
+            ));
+        }
+    }
     match state.get_account_by_id_mut(&to) {
+        Some(account) if account.minted => Err(format!("Initial supply already minted: {}", to)),
         Some(account) => {
-            account.balance += amount;
+            account.balance = account.balance.checked_add(amount)?;
+            account.minted = true;
             Ok(())
         }
-        None => Err("Invalid account.".to_string()),
+        None => Err(format!("Account not found: {}", to)),
     }
 }
 
+/// Read-only part of a transfer, cheap enough to run before the signature check.
+/// Returns the amount to debit.
+fn check_transfer<T: WorldStateRead>(
+    state: &T,
+    from: &AccountId,
+    to: &AccountId,
+    amount: Balance,
+    fee: Balance,
+) -> Result<Balance, Error> {
+    if amount > Balance::ZERO && amount < state.min_transfer() {
+        return Err(format!(
+            "Transfer amount {} is below the minimum of {}",
+            amount,
+            state.min_transfer()
+        ));
+    }
+    if !state.account_exists(to) {
+        return Err("Invalid receiver address.".to_string());
+    }
+    let sender = state
+        .get_account_by_id(from)
+        .ok_or_else(|| "Account `from` not exist.".to_string())?;
+    if sender.frozen {
+        return Err(format!("Account is frozen: {}", from));
+    }
+    let total = amount.checked_add(fee)?;
+    if sender.balance < total {
+        return Err("Insufficient balance".to_string());
+    }
+    Ok(total)
+}
+
 // TODO Task 1: Transfer
 fn transfer<T: WorldState>(
     state: &mut T,
     from: AccountId,
     to: AccountId,
     amount: Balance,
+    fee: Balance,
 ) -> Result<(), Error> {
-    state.get_account_by_id_mut(&from).map_or(
-        Err("Invalid sender address.".to_string()),
-        |acc| {
-            acc.balance.checked_sub(amount).map_or(
-                Err("Insufficient balance".to_string()),
-                |new_amount| {
-                    acc.balance = new_amount;
-                    Ok(())
-                },
-            )
-        },
-    )?;
-
-    state.get_account_by_id_mut(&to).map_or(
-        Err("Invalid receiver address.".to_string()),
-        |acc| {
-            acc.balance.checked_add(amount).map_or(
-                Err("Balance overflow.".to_string()),
-                |new_amount| {
-                    acc.balance = new_amount;
-                    Ok(())
-                },
-            )
-        },
-    )?;
+    let total = check_transfer(state, &from, &to, amount, fee)?;
+    debit(state, &from, total)?;
+
+    let receiver = state
+        .get_account_by_id_mut(&to)
+        .ok_or_else(|| "Invalid receiver address.".to_string())?;
+    receiver.balance = receiver.balance.checked_add(amount)?;
+    Ok(())
+}
+
+fn debit<T: WorldState>(state: &mut T, from: &AccountId, amount: Balance) -> Result<(), Error> {
+    if state
+        .get_account_by_id(from)
+        .is_some_and(|account| account.frozen)
+    {
+        return Err(format!("Account is frozen: {}", from));
+    }
 
+    let sender = state
+        .get_account_by_id_mut(from)
+        .ok_or_else(|| "Invalid sender address.".to_string())?;
+    sender.balance = sender
+        .balance
+        .checked_sub(amount)
+        .map_err(|_| "Insufficient balance".to_string())?;
     Ok(())
 }
 
+/// Burns the creation fee from the funder, nothing to pay in genesis
+fn pay_account_creation_fee<T: WorldState>(
+    state: &mut T,
+    funder: Option<AccountId>,
+    is_genesis: bool,
+) -> Result<(), Error> {
+    let fee = state.account_creation_fee();
+    if is_genesis || fee == Balance::ZERO {
+        return Ok(());
+    }
+    let funder = funder.ok_or_else(|| format!("Account creation fee of {} needs a funder", fee))?;
+    debit(state, &funder, fee)
+}
+
+fn set_frozen<T: WorldState>(
+    state: &mut T,
+    from: AccountId,
+    target: AccountId,
+    frozen: bool,
+) -> Result<(), Error> {
+    if state.admin() != Some(&from) {
+        return Err("Only the admin account can freeze accounts.".to_string());
+    }
+    match state.get_account_by_id_mut(&target) {
+        Some(account) => {
+            account.frozen = frozen;
+            Ok(())
+        }
+        None => Err(format!("Account not found: {}", target)),
+    }
+}
+
+fn create_multisig_account<T: WorldState>(
+    state: &mut T,
+    account_id: AccountId,
+    signers: Vec<PublicKey>,
+    threshold: usize,
+) -> Result<(), Error> {
+    let account_id = normalize_account_id(&account_id)?;
+    if threshold == 0 || threshold > signers.len() {
+        return Err(format!(
+            "Invalid multisig threshold: {} of {} signers",
+            threshold,
+            signers.len()
+        ));
+    }
+    for (i, signer) in signers.iter().enumerate() {
+        if signers[..i].contains(signer) {
+            return Err(format!(
+                "Duplicate multisig signer: {}",
+                hex::encode(signer)
+            ));
+        }
+    }
+    // The account key is never used to sign, the signers' keys are
+    let public_key = signers[0];
+    state.create_account(
+        account_id,
+        AccountType::Multisig { signers, threshold },
+        public_key,
+    )
+}
+
+fn check_message(payload: &[u8]) -> Result<(), Error> {
+    if payload.len() > MAX_MESSAGE_LEN {
+        return Err(format!(
+            "Message is too large: {} > {} bytes",
+            payload.len(),
+            MAX_MESSAGE_LEN
+        ));
+    }
+    Ok(())
+}
+
+fn set_metadata<T: WorldState>(
+    state: &mut T,
+    from: AccountId,
+    key: String,
+    value: String,
+) -> Result<(), Error> {
+    if value.len() > MAX_METADATA_VALUE_LEN {
+        return Err(format!(
+            "Metadata value is too large: {} > {} bytes",
+            value.len(),
+            MAX_METADATA_VALUE_LEN
+        ));
+    }
+    let account = state
+        .get_account_by_id_mut(&from)
+        .ok_or_else(|| format!("Account not found: {}", from))?;
+    if value.is_empty() {
+        account.metadata.remove(&key);
+        return Ok(());
+    }
+
+    let replaced = account
+        .metadata
+        .get(&key)
+        .map_or(0, |old| key.len() + old.len());
+    let len = account.metadata_len() - replaced + key.len() + value.len();
+    if len > MAX_METADATA_LEN {
+        return Err(format!(
+            "Account metadata is too large: {} > {} bytes",
+            len, MAX_METADATA_LEN
+        ));
+    }
+    account.metadata.insert(key, value);
+    Ok(())
+}
+
+fn rotate_key<T: WorldState>(
+    state: &mut T,
+    from: AccountId,
+    new_public_key: PublicKey,
+) -> Result<(), Error> {
+    match state.get_account_by_id_mut(&from) {
+        Some(account) => {
+            account.public_key = new_public_key;
+            Ok(())
+        }
+        None => Err(format!("Account not found: {}", from)),
+    }
+}
+
 impl Transaction {
     pub fn new(data: TransactionData, from: Option<AccountId>) -> Self {
+        Self::with_hasher(data, from, Blake2sHasher)
+    }
+}
+
+impl<H: Hasher> Transaction<H> {
+    pub fn with_hasher(data: TransactionData, from: Option<AccountId>, hasher: H) -> Self {
         Self {
             nonce: 0,
             timestamp: 0,
             data,
             from,
-            signature: None,
+            valid_until: None,
+            signatures: Vec::new(),
+            hasher,
         }
     }
 
@@ -107,68 +547,1166 @@ impl Transaction {
         self.from = Some(from)
     }
 
+    pub fn nonce(&self) -> u128 {
+        self.nonce
+    }
+
+    /// The nonce is part of the signed hash, so signatures made before setting it
+    /// no longer match and are dropped. Sign after the nonce is final.
+    pub fn set_nonce(&mut self, nonce: u128) {
+        if nonce != self.nonce {
+            self.nonce = nonce;
+            self.signatures.clear();
+        }
+    }
+
+    pub fn timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+
+    pub fn valid_until(&self) -> Option<Timestamp> {
+        self.valid_until
+    }
+
+    /// Whether a block with this timestamp can no longer include the transaction
+    pub fn is_expired(&self, timestamp: Timestamp) -> bool {
+        self.valid_until
+            .is_some_and(|valid_until| valid_until < timestamp)
+    }
+
+    pub fn data(&self) -> &TransactionData {
+        &self.data
+    }
+
+    pub fn from(&self) -> Option<&AccountId> {
+        self.from.as_ref()
+    }
+
+    /// The first signature, the only one unless the sender is a multisig account
+    pub fn signature(&self) -> Option<&Signature> {
+        self.signatures.first()
+    }
+
+    pub fn signatures(&self) -> &[Signature] {
+        &self.signatures
+    }
+
+    /// Size in the binary encoding used by `Block::to_bytes`
+    pub fn size_bytes(&self) -> usize {
+        bincode::serialized_size(self).expect("Transaction is always serializable") as usize
+    }
+
+    /// Fee paid by the sender, 0 for transactions without one
+    pub fn fee(&self) -> Balance {
+        match self.data {
+            TransactionData::Transfer { fee, .. } => fee,
+            _ => Balance::ZERO,
+        }
+    }
+
+    /// Fee per byte of `size_bytes`, to compare fees of transactions of different sizes
+    pub fn fee_per_byte(&self) -> f64 {
+        self.fee().value() as f64 / self.size_bytes() as f64
+    }
+
+    /// Whether the account sends this transaction or is affected by it. Ids are
+    /// compared normalized, as they are executed.
+    pub fn involves(&self, account_id: &AccountId) -> bool {
+        let account_id = normalize_account_id(account_id).unwrap_or_else(|_| account_id.clone());
+        let is_account = |id: &AccountId| normalize_account_id(id).is_ok_and(|id| id == account_id);
+        self.from.as_ref().is_some_and(is_account)
+            || match &self.data {
+                TransactionData::CreateAccount(id, _) => is_account(id),
+                TransactionData::Transfer { to, .. } => is_account(to),
+                TransactionData::MintInitialSupply { to, .. } => is_account(to),
+                TransactionData::SetFrozen { target, .. } => is_account(target),
+                TransactionData::RotateKey { .. } => false,
+                TransactionData::CreateMultisigAccount { account_id: id, .. } => is_account(id),
+                TransactionData::Message { .. } | TransactionData::SetMetadata { .. } => false,
+            }
+    }
+
     //TODO Task 2: Signature
+    /// Adds a signature, a multisig sender needs one from each co-signer
     pub fn add_signature(&mut self, signature: Signature) {
-        self.signature = Some(signature);
+        self.signatures.push(signature);
     }
 
+    /// Checks come cheapest first, so spam is turned away before ed25519 runs: a
+    /// transfer's sender, receiver and balance, then the signature, then the rest.
     pub fn execute<T: WorldState>(&self, state: &mut T, is_genesis: bool) -> Result<(), Error> {
+        if let TransactionData::Transfer { to, amount, fee } = &self.data {
+            check_transfer(
+                state,
+                &self.sender()?,
+                &normalize_account_id(to)?,
+                *amount,
+                *fee,
+            )?;
+        }
         //TODO Task 2: Signature
-        if !is_genesis && !matches!(self.data, TransactionData::CreateAccount(_, _)) {
-            if let Err(error) = self.check_signature(state) {
-                return Err(error);
-            }
+        let needs_signature = match self.data {
+            // Only the funder signs, the created account doesn't exist yet
+            TransactionData::CreateAccount(_, _)
+            | TransactionData::CreateMultisigAccount { .. } => self.from.is_some() && !is_genesis,
+            // Spending, admin and key actions and messages are never trusted
+            // without a signature, even in genesis
+            TransactionData::Transfer { .. }
+            | TransactionData::SetFrozen { .. }
+            | TransactionData::RotateKey { .. }
+            | TransactionData::Message { .. }
+            | TransactionData::SetMetadata { .. } => true,
+            TransactionData::MintInitialSupply { .. } => !is_genesis,
+        };
+        if needs_signature {
+            self.check_signature(state)?;
         }
         match &self.data {
             TransactionData::CreateAccount(account_id, public_key) => {
+                pay_account_creation_fee(state, self.funder()?, is_genesis)?;
                 create_account(state, account_id.clone(), *public_key)
             }
             TransactionData::MintInitialSupply { to, amount } => {
-                mint_initial_supply(state, to.clone(), *amount, is_genesis)
+                mint_initial_supply(state, normalize_account_id(to)?, *amount, is_genesis)
             }
-            TransactionData::Transfer { to, amount } => {
+            TransactionData::Transfer { to, amount, fee } => {
                 //TODO Task 1: Transfer
-                transfer(state, self.from.clone().unwrap(), to.clone(), *amount)
+                transfer(
+                    state,
+                    self.sender()?,
+                    normalize_account_id(to)?,
+                    *amount,
+                    *fee,
+                )
+            }
+            TransactionData::SetFrozen { target, frozen } => set_frozen(
+                state,
+                self.sender()?,
+                normalize_account_id(target)?,
+                *frozen,
+            ),
+            TransactionData::RotateKey { new_public_key } => {
+                rotate_key(state, self.sender()?, *new_public_key)
+            }
+            TransactionData::Message { payload } => check_message(payload),
+            TransactionData::SetMetadata { key, value } => {
+                set_metadata(state, self.sender()?, key.clone(), value.clone())
+            }
+            TransactionData::CreateMultisigAccount {
+                account_id,
+                signers,
+                threshold,
+            } => {
+                pay_account_creation_fee(state, self.funder()?, is_genesis)?;
+                create_multisig_account(state, account_id.clone(), signers.clone(), *threshold)
             }
         }
     }
 
-    fn check_signature<T: WorldState>(&self, state: &mut T) -> Result<(), Error> {
+    /// Like `execute`, also reports the balances of the accounts it wrote to
+    pub fn execute_with_receipt<T: WorldState>(
+        &self,
+        state: &mut T,
+        is_genesis: bool,
+    ) -> Result<TransactionReceipt, Error> {
+        let fee = match self.data {
+            TransactionData::CreateAccount(..) | TransactionData::CreateMultisigAccount { .. }
+                if !is_genesis =>
+            {
+                state.account_creation_fee()
+            }
+            _ => self.fee(),
+        };
+
+        let mut journal = Journaled::new(&mut *state);
+        self.execute(&mut journal, is_genesis)?;
+        let snapshot = journal.into_snapshot();
+
+        let balance_of =
+            |account: Option<&Account>| account.map_or(Balance::ZERO, |account| account.balance);
+        Ok(TransactionReceipt {
+            tx_hash: self.hash(),
+            changes: snapshot
+                .entries()
+                .map(|(account_id, before)| BalanceChange {
+                    account_id: account_id.clone(),
+                    before: balance_of(before),
+                    after: balance_of(state.get_account_by_id(account_id)),
+                })
+                .collect(),
+            fee,
+            is_coinbase: matches!(self.data, TransactionData::MintInitialSupply { .. }),
+        })
+    }
+
+    fn check_signature<T: WorldStateRead>(&self, state: &T) -> Result<(), Error> {
         //TODO Task 2: Signature
-        if self.signature.is_none() {
+        if self.signatures.is_empty() {
             return Err("Signature is missing.".to_string());
         }
-        self.from
-            .clone()
-            .map_or(Err("Tx `from` is not defined.".to_string()), |from| {
-                state.get_account_by_id(&from).map_or(
-                    Err("Account `from` not exist.".to_string()),
-                    |account| {
-                        dbg!(self.hash());
-                        if account
-                            .public_key
-                            .verify(
-                                self.hash().as_bytes(),
-                                &ed25519_dalek::Signature::from(self.signature.unwrap()),
-                            )
-                            .is_err()
-                        {
-                            Err("Invalid signature.".to_string())
-                        } else {
-                            Ok(())
-                        }
-                    },
-                )
-            })
+        let from = self.sender()?;
+        state.get_account_by_id(&from).map_or(
+            Err("Account `from` not exist.".to_string()),
+            |account| match &account.account_type {
+                AccountType::Multisig { signers, threshold } => {
+                    // Counted per distinct signer key, so repeating a signature or
+                    // a key listed twice doesn't help
+                    let signed = signers
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, signer)| !signers[..*i].contains(signer))
+                        .filter(|(_, signer)| {
+                            self.signatures.iter().any(|signature| {
+                                self.is_signed_by(state.network_id(), signer, signature)
+                            })
+                        })
+                        .count();
+                    if signed < *threshold {
+                        Err(format!(
+                            "Not enough valid signatures: {} of {}",
+                            signed, threshold
+                        ))
+                    } else {
+                        Ok(())
+                    }
+                }
+                _ => self.verify_against(state.network_id(), &account.public_key),
+            },
+        )
+    }
+
+    /// Checks the first signature against a known key, without any world state.
+    /// The signature has to be made for the chain with `network_id`.
+    pub fn verify_against(&self, network_id: &str, public_key: &PublicKey) -> Result<(), Error> {
+        let signature = self
+            .signatures
+            .first()
+            .ok_or_else(|| "Signature is missing.".to_string())?;
+        if !self.is_signed_by(network_id, public_key, signature) {
+            log::warn!("Invalid signature of transaction {}", self.hash());
+            return Err("Invalid signature.".to_string());
+        }
+        Ok(())
+    }
+
+    /// The sender of transactions that have to be sent by an account, normalized
+    fn sender(&self) -> Result<AccountId, Error> {
+        let from = self
+            .from
+            .as_ref()
+            .ok_or_else(|| MISSING_SENDER.to_string())?;
+        normalize_account_id(from)
+    }
+
+    /// The normalized sender, if any, paying for a created account
+    fn funder(&self) -> Result<Option<AccountId>, Error> {
+        self.from.as_deref().map(normalize_account_id).transpose()
+    }
+
+    fn is_signed_by(
+        &self,
+        network_id: &str,
+        public_key: &PublicKey,
+        signature: &Signature,
+    ) -> bool {
+        verify_hash(public_key, network_id, &self.hash(), signature)
+    }
+}
+
+/// Builds a signed transaction. The signature covers the hash, so it's made
+/// only after every hashed field is set.
+#[derive(Debug, Clone)]
+pub struct TransactionBuilder<H = Blake2sHasher> {
+    data: TransactionData,
+    from: Option<AccountId>,
+    nonce: u128,
+    valid_until: Option<Timestamp>,
+    hasher: H,
+}
+
+impl TransactionBuilder {
+    pub fn new(data: TransactionData) -> Self {
+        Self::with_hasher(data, Blake2sHasher)
+    }
+}
+
+impl<H: Hasher> TransactionBuilder<H> {
+    pub fn with_hasher(data: TransactionData, hasher: H) -> Self {
+        Self {
+            data,
+            from: None,
+            nonce: 0,
+            valid_until: None,
+            hasher,
+        }
+    }
+
+    pub fn from(mut self, from: AccountId) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    pub fn nonce(mut self, nonce: u128) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    pub fn valid_until(mut self, valid_until: Timestamp) -> Self {
+        self.valid_until = Some(valid_until);
+        self
+    }
+
+    /// Signs for the chain with `network_id`, see `WorldStateRead::network_id`
+    pub fn sign(self, keypair: &Keypair, network_id: &str) -> Transaction<H> {
+        let mut tx = Transaction::with_hasher(self.data, self.from, self.hasher);
+        tx.nonce = self.nonce;
+        tx.valid_until = self.valid_until;
+        tx.add_signature(sign_hash(keypair, network_id, &tx.hash()));
+        tx
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use ed25519_dalek::Keypair;
     use rand::rngs::OsRng;
 
     use super::*;
+    use crate::types::Blockchain;
+    use crate::utils::{create_account_tx, sign_hash};
+
+    #[test]
+    fn test_fee_per_byte() {
+        let transfer = |to: &str| {
+            Transaction::new(
+                TransactionData::Transfer {
+                    to: to.to_string(),
+                    amount: 10.into(),
+                    fee: 100.into(),
+                },
+                Some("alice".to_string()),
+            )
+        };
+        let short = transfer("bob");
+        let long = transfer(&"b".repeat(40));
+
+        assert_eq!(short.fee(), long.fee());
+        assert!(short.fee_per_byte() > long.fee_per_byte());
+        assert_eq!(short.fee_per_byte(), 100.0 / short.size_bytes() as f64);
+
+        let create_account = create_account_tx("carol".to_string());
+        assert_eq!(create_account.fee(), Balance::ZERO);
+        assert_eq!(create_account.fee_per_byte(), 0.0);
+    }
+
+    #[test]
+    fn test_verify_against() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let data = TransactionData::CreateAccount("alice".to_string(), keypair.public);
+
+        let tx = TransactionBuilder::new(data.clone()).sign(&keypair, "");
+        assert_eq!(tx.verify_against("", &keypair.public), Ok(()));
+        assert_eq!(
+            tx.verify_against("", &Keypair::generate(&mut OsRng {}).public),
+            Err("Invalid signature.".to_string())
+        );
+        assert_eq!(
+            tx.verify_against("other", &keypair.public),
+            Err("Invalid signature.".to_string())
+        );
+        assert_eq!(
+            Transaction::new(data, None).verify_against("", &keypair.public),
+            Err("Signature is missing.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_signature_bound_to_network() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let setup = |network_id: &str| {
+            let mut state = Blockchain::new();
+            state.network_id = network_id.to_string();
+            create_account(&mut state, "alice".to_string(), keypair.public).unwrap();
+            state
+        };
+        let mut chain_a = setup("chain-a");
+        let mut chain_b = setup("chain-b");
+        let tx = TransactionBuilder::new(TransactionData::SetMetadata {
+            key: "name".to_string(),
+            value: "alice".to_string(),
+        })
+        .from("alice".to_string())
+        .sign(&keypair, "chain-a");
+
+        assert_eq!(
+            tx.execute(&mut chain_b, false),
+            Err("Invalid signature.".to_string())
+        );
+        assert!(tx.execute(&mut chain_a, false).is_ok());
+    }
+
+    #[test]
+    fn test_create_account_normalizes_id() {
+        let mut state = Blockchain::new();
+        let keypair = Keypair::generate(&mut OsRng {});
+
+        assert!(create_account(&mut state, " Alice ".to_string(), keypair.public).is_ok());
+        assert!(state.get_account_by_id(&"alice".to_string()).is_some());
+        assert_eq!(
+            create_account(&mut state, "ALICE".to_string(), keypair.public),
+            Err("AccountId already exist: alice".to_string())
+        );
+        assert_eq!(
+            create_account(&mut state, "al ice".to_string(), keypair.public),
+            Err("Invalid account id: \"al ice\" has illegal characters".to_string())
+        );
+    }
+
+    #[test]
+    fn test_execute_normalizes_ids() {
+        let mut state = Blockchain::new();
+        let alice = Keypair::generate(&mut OsRng {});
+        let bob = Keypair::generate(&mut OsRng {});
+        let genesis = [
+            TransactionData::CreateAccount(" Alice ".to_string(), alice.public),
+            TransactionData::CreateAccount("bob".to_string(), bob.public),
+            TransactionData::MintInitialSupply {
+                to: " Alice ".to_string(),
+                amount: 100.into(),
+            },
+        ];
+        for data in genesis {
+            assert!(Transaction::new(data, None)
+                .execute(&mut state, true)
+                .is_ok());
+        }
+
+        let transfer = TransactionBuilder::new(TransactionData::Transfer {
+            to: "BOB".to_string(),
+            amount: 30.into(),
+            fee: 0.into(),
+        })
+        .from("Alice".to_string())
+        .sign(&alice, state.network_id());
+        assert!(transfer.clone().execute(&mut state, false).is_ok());
+        assert_eq!(state.balance_of(&"alice".to_string()), Some(70.into()));
+        assert_eq!(state.balance_of(&"Bob".to_string()), Some(30.into()));
+        assert!(transfer.involves(&"ALICE".to_string()));
+        assert!(transfer.involves(&"bob".to_string()));
+    }
+
+    #[test]
+    fn test_account_exists() {
+        let mut state = Blockchain::new();
+        let keypair = Keypair::generate(&mut OsRng {});
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+        assert!(!state.account_exists(&alice));
+
+        create_account(&mut state, alice.clone(), keypair.public).unwrap();
+        mint_initial_supply(&mut state, alice.clone(), 100.into(), true).unwrap();
+        assert!(state.account_exists(&alice));
+        assert!(!state.account_exists(&bob));
+
+        // The sender isn't charged when the receiver is missing
+        assert_eq!(
+            transfer(&mut state, alice.clone(), bob, 10.into(), 1.into()),
+            Err("Invalid receiver address.".to_string())
+        );
+        assert_eq!(state.get_account_by_id(&alice).unwrap().balance, 100);
+    }
+
+    #[test]
+    fn test_create_account_requires_derived_id() {
+        let mut state = Blockchain::new();
+        state.require_derived_account_ids = true;
+        let keypair = Keypair::generate(&mut OsRng {});
+
+        assert_eq!(
+            create_account(&mut state, "satoshi".to_string(), keypair.public),
+            Err("Account id satoshi isn't derived from its public key".to_string())
+        );
+        let account_id = derive_account_id(&keypair.public);
+        assert!(create_account(&mut state, account_id.clone(), keypair.public).is_ok());
+        assert!(state.get_account_by_id(&account_id).is_some());
+    }
+
+    #[test]
+    fn test_size_bytes() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let create_account = Transaction::new(
+            TransactionData::CreateAccount("alice".to_string(), keypair.public),
+            None,
+        );
+        let transfer = Transaction::new(
+            TransactionData::Transfer {
+                to: "alice".to_string(),
+                amount: 10.into(),
+                fee: 0.into(),
+            },
+            None,
+        );
+        // A length-prefixed 32-byte key instead of two u128 amounts
+        assert_eq!(
+            create_account.size_bytes() - transfer.size_bytes(),
+            (8 + 32) - (16 + 16)
+        );
+
+        let signed = TransactionBuilder::new(transfer.data.clone()).sign(&keypair, "");
+        // One length-prefixed 64-byte signature in the signature list
+        assert_eq!(signed.size_bytes() - transfer.size_bytes(), 8 + 64);
+        assert_eq!(
+            signed.size_bytes(),
+            bincode::serialize(&signed).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_mint_overflow() {
+        let mut state = Blockchain::new();
+        let keypair = Keypair::generate(&mut OsRng {});
+        create_account(&mut state, "satoshi".to_string(), keypair.public).unwrap();
+        create_account(&mut state, "alice".to_string(), keypair.public).unwrap();
+
+        assert!(mint_initial_supply(&mut state, "satoshi".to_string(), Balance::MAX, true).is_ok());
+        transfer(
+            &mut state,
+            "satoshi".to_string(),
+            "alice".to_string(),
+            Balance::MAX,
+            0.into(),
+        )
+        .unwrap();
+        assert_eq!(
+            mint_initial_supply(&mut state, "alice".to_string(), 1.into(), true),
+            Err("Balance overflow.".to_string())
+        );
+        assert_eq!(
+            state
+                .get_account_by_id(&"alice".to_string())
+                .unwrap()
+                .balance,
+            Balance::MAX
+        );
+    }
+
+    #[test]
+    fn test_min_transfer() {
+        let mut state = Blockchain::new();
+        state.consensus.min_transfer = 10.into();
+        let keypair = Keypair::generate(&mut OsRng {});
+        create_account(&mut state, "satoshi".to_string(), keypair.public).unwrap();
+        create_account(&mut state, "alice".to_string(), keypair.public).unwrap();
+        mint_initial_supply(&mut state, "alice".to_string(), 100.into(), true).unwrap();
+
+        let alice = "alice".to_string();
+        let satoshi = "satoshi".to_string();
+        assert_eq!(
+            transfer(
+                &mut state,
+                alice.clone(),
+                satoshi.clone(),
+                5.into(),
+                0.into()
+            ),
+            Err("Transfer amount 5 is below the minimum of 10".to_string())
+        );
+        assert!(transfer(
+            &mut state,
+            alice.clone(),
+            satoshi.clone(),
+            10.into(),
+            0.into()
+        )
+        .is_ok());
+        // Zero amount only pays the fee
+        assert!(transfer(&mut state, alice, satoshi.clone(), 0.into(), 1.into()).is_ok());
+        assert_eq!(state.get_account_by_id(&satoshi).unwrap().balance, 10);
+    }
+
+    #[test]
+    fn test_mint_twice() {
+        let mut state = Blockchain::new();
+        let keypair = Keypair::generate(&mut OsRng {});
+        create_account(&mut state, "satoshi".to_string(), keypair.public).unwrap();
+
+        assert!(mint_initial_supply(&mut state, "satoshi".to_string(), 100.into(), true).is_ok());
+        assert_eq!(
+            mint_initial_supply(&mut state, "satoshi".to_string(), 100.into(), true),
+            Err("Initial supply already minted: satoshi".to_string())
+        );
+        assert_eq!(
+            state
+                .get_account_by_id(&"satoshi".to_string())
+                .unwrap()
+                .balance,
+            100
+        );
+    }
+
+    #[test]
+    fn test_verify_self_signed_create_account() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let mut tx = Transaction::new(
+            TransactionData::CreateAccount("alice".to_string(), keypair.public),
+            None,
+        );
+        assert!(tx.verify("main"));
+
+        tx.add_signature(sign_hash(&keypair, "main", &tx.hash()));
+        assert!(tx.verify("main"));
+        assert!(!tx.verify("test"));
+
+        let other = Keypair::generate(&mut OsRng {});
+        tx.add_signature(sign_hash(&other, "main", &tx.hash()));
+        assert!(!tx.verify("main"));
+    }
+
+    #[test]
+    fn test_verify_signed_transfer() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let mut tx = Transaction::new(
+            TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount: 10.into(),
+                fee: 0.into(),
+            },
+            Some("alice".to_string()),
+        );
+        tx.add_signature(sign_hash(&keypair, "main", &tx.hash()));
+        assert!(tx.verify("main"));
+
+        tx.from = None;
+        assert!(!tx.verify("main"));
+    }
+
+    #[test]
+    fn test_accessors() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let data = TransactionData::Transfer {
+            to: "bob".to_string(),
+            amount: 10.into(),
+            fee: 0.into(),
+        };
+        let mut tx = Transaction::new(data.clone(), Some("alice".to_string()));
+        assert_eq!(tx.signature(), None);
+
+        let signature = sign_hash(&keypair, "main", &tx.hash());
+        tx.add_signature(signature);
+
+        assert_eq!(tx.nonce(), 0);
+        assert_eq!(tx.data(), &data);
+        assert_eq!(tx.from(), Some(&"alice".to_string()));
+        assert_eq!(tx.signature(), Some(&signature));
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut state = Blockchain::new();
+        let keypair = Keypair::generate(&mut OsRng {});
+        create_account(&mut state, "alice".to_string(), keypair.public).unwrap();
+        create_account(&mut state, "bob".to_string(), keypair.public).unwrap();
+        mint_initial_supply(&mut state, "alice".to_string(), 100.into(), true).unwrap();
+
+        let tx = TransactionBuilder::new(TransactionData::Transfer {
+            to: "bob".to_string(),
+            amount: 10.into(),
+            fee: 0.into(),
+        })
+        .from("alice".to_string())
+        .nonce(5)
+        .sign(&keypair, state.network_id());
+
+        assert_eq!(tx.nonce(), 5);
+        assert_eq!(tx.from(), Some(&"alice".to_string()));
+        assert!(tx.check_signature(&state).is_ok());
+        assert!(tx.execute(&mut state, false).is_ok());
+        assert_eq!(
+            state.get_account_by_id(&"bob".to_string()).unwrap().balance,
+            10
+        );
+    }
+
+    fn multisig_transfer(keypairs: &[&Keypair]) -> Transaction {
+        let mut tx = Transaction::new(
+            TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount: 10.into(),
+                fee: 0.into(),
+            },
+            Some("vault".to_string()),
+        );
+        for keypair in keypairs {
+            tx.add_signature(sign_hash(keypair, "", &tx.hash()));
+        }
+        tx
+    }
+
+    #[test]
+    fn test_multisig_2_of_3() {
+        let mut state = Blockchain::new();
+        let keypairs = (0..3)
+            .map(|_| Keypair::generate(&mut OsRng {}))
+            .collect::<Vec<_>>();
+        create_multisig_account(
+            &mut state,
+            "vault".to_string(),
+            keypairs.iter().map(|keypair| keypair.public).collect(),
+            2,
+        )
+        .unwrap();
+        create_account(&mut state, "bob".to_string(), keypairs[0].public).unwrap();
+        mint_initial_supply(&mut state, "vault".to_string(), 100.into(), true).unwrap();
+
+        let tx = multisig_transfer(&[&keypairs[0], &keypairs[2]]);
+        assert!(tx.execute(&mut state, false).is_ok());
+        assert_eq!(
+            state.get_account_by_id(&"bob".to_string()).unwrap().balance,
+            10
+        );
+
+        assert_eq!(
+            multisig_transfer(&[&keypairs[1]]).check_signature(&state),
+            Err("Not enough valid signatures: 1 of 2".to_string())
+        );
+        assert_eq!(
+            multisig_transfer(&[&keypairs[1], &keypairs[1]]).check_signature(&state),
+            Err("Not enough valid signatures: 1 of 2".to_string())
+        );
+        let outsider = Keypair::generate(&mut OsRng {});
+        assert!(multisig_transfer(&[&keypairs[1], &outsider])
+            .check_signature(&state)
+            .is_err());
+    }
+
+    #[test]
+    fn test_create_multisig_account_threshold() {
+        let mut state = Blockchain::new();
+        let keypair = Keypair::generate(&mut OsRng {});
+
+        assert_eq!(
+            create_multisig_account(&mut state, "vault".to_string(), vec![keypair.public], 2),
+            Err("Invalid multisig threshold: 2 of 1 signers".to_string())
+        );
+        assert!(
+            create_multisig_account(&mut state, "vault".to_string(), vec![keypair.public], 0)
+                .is_err()
+        );
+        assert!(state.get_account_by_id(&"vault".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_multisig_duplicate_signer() {
+        let mut state = Blockchain::new();
+        let keypair = Keypair::generate(&mut OsRng {});
+
+        assert_eq!(
+            create_multisig_account(
+                &mut state,
+                "vault".to_string(),
+                vec![keypair.public, keypair.public],
+                2
+            ),
+            Err(format!(
+                "Duplicate multisig signer: {}",
+                hex::encode(keypair.public)
+            ))
+        );
+        assert!(state.get_account_by_id(&"vault".to_string()).is_none());
+
+        // An account that already lists a key twice still needs two distinct keys
+        state
+            .create_account(
+                "vault".to_string(),
+                AccountType::Multisig {
+                    signers: vec![keypair.public, keypair.public],
+                    threshold: 2,
+                },
+                keypair.public,
+            )
+            .unwrap();
+        assert_eq!(
+            multisig_transfer(&[&keypair]).check_signature(&state),
+            Err("Not enough valid signatures: 1 of 2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_signatures_serde() {
+        let keypairs = (0..2)
+            .map(|_| Keypair::generate(&mut OsRng {}))
+            .collect::<Vec<_>>();
+        let tx = multisig_transfer(&[&keypairs[0], &keypairs[1]]);
+
+        let decoded: Transaction =
+            serde_json::from_str(&serde_json::to_string(&tx).unwrap()).unwrap();
+        assert_eq!(decoded.signatures(), tx.signatures());
+        let decoded: Transaction = bincode::deserialize(&bincode::serialize(&tx).unwrap()).unwrap();
+        assert_eq!(decoded.signatures(), tx.signatures());
+    }
+
+    #[test]
+    fn test_execute_without_sender() {
+        let mut state = Blockchain::new();
+        let keypair = Keypair::generate(&mut OsRng {});
+        create_account(&mut state, "alice".to_string(), keypair.public).unwrap();
+        mint_initial_supply(&mut state, "alice".to_string(), 100.into(), true).unwrap();
+
+        let mut tx = Transaction::new(
+            TransactionData::Transfer {
+                to: "alice".to_string(),
+                amount: 10.into(),
+                fee: 0.into(),
+            },
+            None,
+        );
+        assert_eq!(
+            tx.execute(&mut state, false),
+            Err(MISSING_SENDER.to_string())
+        );
+        tx.add_signature(sign_hash(&keypair, state.network_id(), &tx.hash()));
+        for is_genesis in [true, false] {
+            assert_eq!(
+                tx.execute(&mut state, is_genesis),
+                Err(MISSING_SENDER.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_transfer_checks_balance_before_signature() {
+        let mut state = Blockchain::new();
+        let alice = Keypair::generate(&mut OsRng {});
+        let mallory = Keypair::generate(&mut OsRng {});
+        create_account(&mut state, "alice".to_string(), alice.public).unwrap();
+        create_account(&mut state, "bob".to_string(), mallory.public).unwrap();
+
+        // Signed with the wrong key, yet the missing funds are reported first
+        let transfer = TransactionBuilder::new(TransactionData::Transfer {
+            to: "bob".to_string(),
+            amount: 10.into(),
+            fee: 1.into(),
+        })
+        .from("alice".to_string());
+        let forged = transfer.clone().sign(&mallory, state.network_id());
+        assert_eq!(
+            forged.execute(&mut state, false),
+            Err("Insufficient balance".to_string())
+        );
+        let mut to_nobody = forged.clone();
+        to_nobody.data = TransactionData::Transfer {
+            to: "carol".to_string(),
+            amount: 10.into(),
+            fee: 1.into(),
+        };
+        assert_eq!(
+            to_nobody.execute(&mut state, false),
+            Err("Invalid receiver address.".to_string())
+        );
+
+        mint_initial_supply(&mut state, "alice".to_string(), 11.into(), true).unwrap();
+        assert_eq!(
+            forged.execute(&mut state, false),
+            Err("Invalid signature.".to_string())
+        );
+        assert!(transfer
+            .sign(&alice, state.network_id())
+            .execute(&mut state, false)
+            .is_ok());
+        assert_eq!(
+            state
+                .get_account_by_id(&"alice".to_string())
+                .unwrap()
+                .balance,
+            0
+        );
+    }
+
+    #[test]
+    fn test_genesis_transfer_needs_signature() {
+        let mut state = Blockchain::new();
+        let alice = Keypair::generate(&mut OsRng {});
+        let mallory = Keypair::generate(&mut OsRng {});
+        create_account(&mut state, "alice".to_string(), alice.public).unwrap();
+        create_account(&mut state, "bob".to_string(), mallory.public).unwrap();
+        mint_initial_supply(&mut state, "alice".to_string(), 100.into(), true).unwrap();
+
+        let transfer = TransactionBuilder::new(TransactionData::Transfer {
+            to: "bob".to_string(),
+            amount: 10.into(),
+            fee: 0.into(),
+        })
+        .from("alice".to_string());
+        let mut unsigned = transfer.clone().sign(&alice, state.network_id());
+        unsigned.signatures.clear();
+        assert_eq!(
+            unsigned.execute(&mut state, true),
+            Err("Signature is missing.".to_string())
+        );
+        assert_eq!(
+            transfer
+                .clone()
+                .sign(&mallory, state.network_id())
+                .execute(&mut state, true),
+            Err("Invalid signature.".to_string())
+        );
+        assert_eq!(
+            state.get_account_by_id(&"bob".to_string()).unwrap().balance,
+            0
+        );
+
+        assert!(transfer
+            .sign(&alice, state.network_id())
+            .execute(&mut state, true)
+            .is_ok());
+        assert_eq!(
+            state.get_account_by_id(&"bob".to_string()).unwrap().balance,
+            10
+        );
+    }
+
+    #[test]
+    fn test_account_creation_fee() {
+        let mut state = Blockchain::new();
+        state.account_creation_fee = 5.into();
+        let alice = Keypair::generate(&mut OsRng {});
+        create_account(&mut state, "alice".to_string(), alice.public).unwrap();
+        mint_initial_supply(&mut state, "alice".to_string(), 8.into(), true).unwrap();
+
+        let bob = Keypair::generate(&mut OsRng {});
+        let tx = TransactionBuilder::new(TransactionData::CreateAccount(
+            "bob".to_string(),
+            bob.public,
+        ))
+        .from("alice".to_string())
+        .sign(&alice, state.network_id());
+        assert!(tx.verify(state.network_id()));
+        assert!(tx.execute(&mut state, false).is_ok());
+        assert!(state.get_account_by_id(&"bob".to_string()).is_some());
+        assert_eq!(
+            state
+                .get_account_by_id(&"alice".to_string())
+                .unwrap()
+                .balance,
+            3
+        );
+
+        let tx = TransactionBuilder::new(TransactionData::CreateAccount(
+            "carol".to_string(),
+            bob.public,
+        ))
+        .from("alice".to_string())
+        .sign(&alice, state.network_id());
+        assert_eq!(
+            tx.execute(&mut state, false),
+            Err("Insufficient balance".to_string())
+        );
+        assert!(state.get_account_by_id(&"carol".to_string()).is_none());
+        assert_eq!(
+            state
+                .get_account_by_id(&"alice".to_string())
+                .unwrap()
+                .balance,
+            3
+        );
+    }
+
+    #[test]
+    fn test_account_creation_fee_needs_funder() {
+        let mut state = Blockchain::new();
+        state.account_creation_fee = 5.into();
+        let keypair = Keypair::generate(&mut OsRng {});
+        let tx = Transaction::new(
+            TransactionData::CreateAccount("alice".to_string(), keypair.public),
+            None,
+        );
+
+        assert_eq!(
+            tx.execute(&mut state, false),
+            Err("Account creation fee of 5 needs a funder".to_string())
+        );
+        // Genesis accounts are free
+        assert!(tx.execute(&mut state, true).is_ok());
+    }
+
+    #[test]
+    fn test_message() {
+        let mut state = Blockchain::new();
+        let keypair = Keypair::generate(&mut OsRng {});
+        create_account(&mut state, "alice".to_string(), keypair.public).unwrap();
+        let network_id = state.network_id().to_string();
+        let message = |len| {
+            TransactionBuilder::new(TransactionData::Message {
+                payload: vec![7; len],
+            })
+            .from("alice".to_string())
+            .sign(&keypair, &network_id)
+        };
+
+        assert!(message(MAX_MESSAGE_LEN).execute(&mut state, false).is_ok());
+        assert_eq!(
+            message(MAX_MESSAGE_LEN + 1).execute(&mut state, false),
+            Err("Message is too large: 257 > 256 bytes".to_string())
+        );
+        // The payload is hashed, so a block can't be altered to carry another one
+        assert_ne!(message(1).hash(), message(2).hash());
+
+        let mut unsigned = message(1);
+        unsigned.signatures.clear();
+        assert_eq!(
+            unsigned.execute(&mut state, true),
+            Err("Signature is missing.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let transfer = Transaction::new(
+            TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount: 10.into(),
+                fee: 1.into(),
+            },
+            Some("alice".to_string()),
+        );
+        assert_eq!(transfer.to_string(), "Transfer 10 from alice to bob, fee 1");
+
+        let create_account = Transaction::new(
+            TransactionData::CreateAccount("alice".to_string(), keypair.public),
+            None,
+        );
+        assert_eq!(
+            create_account.to_string(),
+            format!(
+                "CreateAccount alice with key {}…",
+                &hex::encode(keypair.public)[..8]
+            )
+        );
+    }
+
+    #[test]
+    fn test_set_metadata() {
+        let mut state = Blockchain::new();
+        let keypair = Keypair::generate(&mut OsRng {});
+        create_account(&mut state, "alice".to_string(), keypair.public).unwrap();
+        let network_id = state.network_id().to_string();
+        let set_name = |name: &str| {
+            TransactionBuilder::new(TransactionData::SetMetadata {
+                key: "name".to_string(),
+                value: name.to_string(),
+            })
+            .from("alice".to_string())
+            .sign(&keypair, &network_id)
+        };
+        let name = |state: &Blockchain| {
+            state
+                .get_account_by_id(&"alice".to_string())
+                .unwrap()
+                .metadata()
+                .get("name")
+                .cloned()
+        };
+
+        assert!(set_name("Alice").execute(&mut state, false).is_ok());
+        assert_eq!(name(&state), Some("Alice".to_string()));
+        assert!(set_name("Alice Smith").execute(&mut state, false).is_ok());
+        assert_eq!(name(&state), Some("Alice Smith".to_string()));
+
+        assert_eq!(
+            set_name(&"a".repeat(MAX_METADATA_VALUE_LEN + 1)).execute(&mut state, false),
+            Err("Metadata value is too large: 257 > 256 bytes".to_string())
+        );
+        assert_eq!(name(&state), Some("Alice Smith".to_string()));
+
+        assert!(set_name("").execute(&mut state, false).is_ok());
+        assert_eq!(name(&state), None);
+    }
+
+    #[test]
+    fn test_metadata_total_size() {
+        let mut state = Blockchain::new();
+        let keypair = Keypair::generate(&mut OsRng {});
+        create_account(&mut state, "alice".to_string(), keypair.public).unwrap();
+        let alice = "alice".to_string();
+        let value = "v".repeat(MAX_METADATA_VALUE_LEN - 1);
+
+        // Each entry takes 1 + 255 bytes
+        for key in ["a", "b", "c", "d"] {
+            set_metadata(&mut state, alice.clone(), key.to_string(), value.clone()).unwrap();
+        }
+        assert_eq!(
+            set_metadata(&mut state, alice.clone(), "e".to_string(), "v".to_string()),
+            Err("Account metadata is too large: 1026 > 1024 bytes".to_string())
+        );
+        // Overwriting frees the old value first
+        assert!(set_metadata(&mut state, alice, "a".to_string(), value).is_ok());
+    }
+
+    #[test]
+    fn test_set_nonce() {
+        let mut state = Blockchain::new();
+        let keypair = Keypair::generate(&mut OsRng {});
+        create_account(&mut state, "alice".to_string(), keypair.public).unwrap();
+        let message = Transaction::new(
+            TransactionData::Message { payload: vec![1] },
+            Some("alice".to_string()),
+        );
+        let mut first = message.clone();
+        first.set_nonce(1);
+        let mut second = message;
+        second.set_nonce(2);
+        assert_ne!(first.hash(), second.hash());
+
+        // A signature of one nonce doesn't cover the other
+        first.add_signature(sign_hash(&keypair, state.network_id(), &first.hash()));
+        second.add_signature(sign_hash(&keypair, state.network_id(), &first.hash()));
+        assert!(first.check_signature(&state).is_ok());
+        assert_eq!(
+            second.check_signature(&state),
+            Err("Invalid signature.".to_string())
+        );
+
+        first.set_nonce(3);
+        assert_eq!(first.nonce(), 3);
+        assert_eq!(first.signature(), None);
+        first.add_signature(sign_hash(&keypair, state.network_id(), &first.hash()));
+        assert!(first.check_signature(&state).is_ok());
+    }
+
+    #[test]
+    fn test_tx_eq() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let tx = Transaction::new(
+            TransactionData::CreateAccount("alice".to_string(), keypair.public),
+            None,
+        );
+        let other = Transaction::new(
+            TransactionData::CreateAccount("bob".to_string(), keypair.public),
+            None,
+        );
+
+        assert_eq!(tx, tx.clone());
+        assert_ne!(tx, other);
+
+        let set = vec![tx.clone(), tx.clone(), other]
+            .into_iter()
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&tx));
+    }
+
+    #[test]
+    fn test_tx_hash_survives_serde() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let tx = Transaction::new(
+            TransactionData::CreateAccount("alice".to_string(), keypair.public),
+            None,
+        );
+        let decoded: Transaction =
+            serde_json::from_str(&serde_json::to_string(&tx).unwrap()).unwrap();
+
+        assert_eq!(Hashable::hash(&decoded), Hashable::hash(&tx));
+    }
 
     #[test]
     fn test_tx_hash_changed() {
@@ -0,0 +1,100 @@
+use ed25519_dalek::{Keypair, PublicKey, SecretKey};
+use rand::{CryptoRng, RngCore};
+
+use crate::traits::{Hashable, Hasher};
+use crate::types::{AccountId, Error, Transaction};
+use crate::utils::{derive_account_id, sign_hash};
+
+/// Keypair of an account with the id derived from its public key
+pub struct Wallet {
+    keypair: Keypair,
+    account_id: AccountId,
+}
+
+impl Wallet {
+    pub fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        Self::from_keypair(Keypair::generate(rng))
+    }
+
+    /// The same secret always gives the same keys and account id
+    pub fn from_secret_bytes(bytes: &[u8; 32]) -> Result<Self, Error> {
+        let secret = SecretKey::from_bytes(bytes).map_err(|error| error.to_string())?;
+        let public = PublicKey::from(&secret);
+        Ok(Self::from_keypair(Keypair { secret, public }))
+    }
+
+    fn from_keypair(keypair: Keypair) -> Self {
+        let account_id = derive_account_id(&keypair.public);
+        Self {
+            keypair,
+            account_id,
+        }
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.keypair.public
+    }
+
+    pub fn account_id(&self) -> &AccountId {
+        &self.account_id
+    }
+
+    pub fn secret_bytes(&self) -> [u8; 32] {
+        self.keypair.secret.to_bytes()
+    }
+
+    /// Adds the wallet's signature of the transaction's current hash, made for
+    /// the chain with `network_id`
+    pub fn sign<H: Hasher>(&self, network_id: &str, tx: &mut Transaction<H>) {
+        tx.add_signature(sign_hash(&self.keypair, network_id, &tx.hash()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::traits::{Verifiable, WorldStateRead};
+    use crate::types::{Blockchain, TransactionData};
+
+    #[test]
+    fn test_from_secret_bytes() {
+        let secret = [7u8; 32];
+        let first = Wallet::from_secret_bytes(&secret).unwrap();
+        let second = Wallet::from_secret_bytes(&secret).unwrap();
+
+        assert_eq!(first.public_key(), second.public_key());
+        assert_eq!(first.account_id(), second.account_id());
+        assert_eq!(first.account_id(), &derive_account_id(&first.public_key()));
+        assert_eq!(first.secret_bytes(), secret);
+
+        let other = Wallet::from_secret_bytes(&[8u8; 32]).unwrap();
+        assert_ne!(first.public_key(), other.public_key());
+    }
+
+    #[test]
+    fn test_restored_wallet_signs() {
+        let wallet = Wallet::generate(&mut OsRng {});
+        let restored = Wallet::from_secret_bytes(&wallet.secret_bytes()).unwrap();
+        let mut state = Blockchain::new();
+
+        let mut tx = Transaction::new(
+            TransactionData::CreateAccount(wallet.account_id().clone(), wallet.public_key()),
+            None,
+        );
+        restored.sign(state.network_id(), &mut tx);
+        assert!(tx.verify(state.network_id()));
+        assert!(tx.execute(&mut state, false).is_ok());
+
+        let mut tx = Transaction::new(
+            TransactionData::SetMetadata {
+                key: "name".to_string(),
+                value: "alice".to_string(),
+            },
+            Some(wallet.account_id().clone()),
+        );
+        restored.sign(state.network_id(), &mut tx);
+        assert!(tx.execute(&mut state, false).is_ok());
+    }
+}
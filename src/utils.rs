@@ -1,24 +1,361 @@
-use crate::types::{AccountId, Balance, Transaction, TransactionData};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::types::{
+    AccountId, Balance, Block, Blockchain, Error, Hash, Signature, Timestamp, Transaction,
+    TransactionData,
+};
 use blake2::{Blake2s, Digest};
-use ed25519_dalek::Keypair;
+use ed25519_dalek::{Keypair, PublicKey, Signer, Verifier};
 use rand::rngs::OsRng;
-use rand::Rng;
+use rand::{CryptoRng, Rng, RngCore};
 
 pub fn create_mint_initial_supply_tx(to: AccountId, amount: Balance) -> TransactionData {
     TransactionData::MintInitialSupply { to, amount }
 }
 
 pub fn create_account_tx(account_id: String) -> Transaction {
-    let keypair = Keypair::generate(&mut OsRng {});
+    create_account_tx_with_rng(account_id, &mut OsRng {})
+}
+
+/// Like `create_account_tx`, a seeded rng makes the key reproducible
+pub fn create_account_tx_with_rng<R: RngCore + CryptoRng>(
+    account_id: String,
+    rng: &mut R,
+) -> Transaction {
+    let keypair = Keypair::generate(rng);
     Transaction::new(
         TransactionData::CreateAccount(account_id, keypair.public),
         None,
     )
 }
 
+/// Chain with a genesis block that creates every account with a fresh key and
+/// mints its balance. Returns the keys by account id, to sign transactions from
+/// those accounts. Panics if the genesis block is rejected, e.g. for a repeated id.
+pub fn bootstrap_chain(accounts: &[(&str, Balance)]) -> (Blockchain, HashMap<AccountId, Keypair>) {
+    let mut keypairs = HashMap::new();
+    let mut genesis = Block::genesis();
+    for (account_id, _) in accounts {
+        let keypair = Keypair::generate(&mut OsRng {});
+        genesis.add_transaction(Transaction::new(
+            TransactionData::CreateAccount(account_id.to_string(), keypair.public),
+            None,
+        ));
+        keypairs.insert(account_id.to_string(), keypair);
+    }
+    for (account_id, balance) in accounts {
+        genesis.add_transaction(Transaction::new(
+            create_mint_initial_supply_tx(account_id.to_string(), *balance),
+            None,
+        ));
+    }
+
+    let mut blockchain = Blockchain::new();
+    if let Err(error) = blockchain.append_block(genesis) {
+        panic!("Genesis block was rejected: {}", error);
+    }
+    (blockchain, keypairs)
+}
+
 pub fn generate_random_account() -> AccountId {
-    let mut rng = rand::thread_rng();
+    generate_random_account_with_rng(&mut OsRng {})
+}
+
+/// Like `generate_random_account`, a seeded rng makes the id reproducible
+pub fn generate_random_account_with_rng<R: RngCore + CryptoRng>(rng: &mut R) -> AccountId {
     let seed: u128 = rng.gen();
 
     hex::encode(Blake2s::digest(&seed.to_be_bytes()))
 }
+
+/// Current time in millis since unix epoch
+pub fn current_timestamp() -> Timestamp {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis())
+}
+
+/// Account id bound to a public key: hex-encoded Blake2s hash of the key
+pub fn derive_account_id(public_key: &PublicKey) -> AccountId {
+    hex::encode(Blake2s::digest(public_key.as_bytes()))
+}
+
+/// Tag in front of every signed message, so transaction signatures can't be
+/// reused for anything else
+pub const SIGNING_DOMAIN: &[u8] = b"my-blockchain-tx-v1";
+
+/// Bytes that get signed for a transaction hash: the domain tag, the network id
+/// with its length and the hash. Signatures made for one network don't verify
+/// on another.
+pub fn signing_message(network_id: &str, hash: &Hash) -> Vec<u8> {
+    let mut message = SIGNING_DOMAIN.to_vec();
+    message.extend((network_id.len() as u32).to_be_bytes());
+    message.extend(network_id.as_bytes());
+    message.extend(hash.as_bytes());
+    message
+}
+
+/// Signs a transaction hash the way `Transaction::add_signature` expects, for
+/// the chain with `network_id`
+pub fn sign_hash(keypair: &Keypair, network_id: &str, hash: &Hash) -> Signature {
+    keypair.sign(&signing_message(network_id, hash)).to_bytes()
+}
+
+pub fn verify_hash(
+    public_key: &PublicKey,
+    network_id: &str,
+    hash: &Hash,
+    signature: &Signature,
+) -> bool {
+    public_key
+        .verify(
+            &signing_message(network_id, hash),
+            &ed25519_dalek::Signature::from(*signature),
+        )
+        .is_ok()
+}
+
+/// Decimal places of a coin, one coin is `10^DECIMALS` base units
+pub const DECIMALS: u32 = 8;
+
+/// Balance in coins, e.g. `1.5` for 150000000 base units. Trailing zeros of
+/// the fraction are left out.
+pub fn format_amount(balance: Balance) -> String {
+    let unit = 10u128.pow(DECIMALS);
+    let (coins, fraction) = (balance.value() / unit, balance.value() % unit);
+    if fraction == 0 {
+        return coins.to_string();
+    }
+    let fraction = format!("{:0width$}", fraction, width = DECIMALS as usize);
+    format!("{}.{}", coins, fraction.trim_end_matches('0'))
+}
+
+/// Parses an amount in coins like `format_amount` prints it
+pub fn parse_amount(s: &str) -> Result<Balance, Error> {
+    let invalid = || format!("Invalid amount: {:?}", s);
+    let (coins, fraction) = match s.split_once('.') {
+        Some((_, "")) => return Err(invalid()),
+        Some((coins, fraction)) => (coins, fraction),
+        None => (s, ""),
+    };
+    if coins.is_empty()
+        || !coins
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+    if fraction.len() > DECIMALS as usize {
+        return Err(format!(
+            "Too many decimal places: {} > {}",
+            fraction.len(),
+            DECIMALS
+        ));
+    }
+
+    let too_large = || format!("Amount is too large: {}", s);
+    let coins = coins.parse::<u128>().map_err(|_| too_large())?;
+    let fraction = format!("{:0<width$}", fraction, width = DECIMALS as usize)
+        .parse::<u128>()
+        .map_err(|_| invalid())?;
+    coins
+        .checked_mul(10u128.pow(DECIMALS))
+        .and_then(|units| units.checked_add(fraction))
+        .map(Balance::from)
+        .ok_or_else(too_large)
+}
+
+/// First 8 characters of a hex string with an ellipsis, for display
+pub fn short_hex(hex: &str) -> String {
+    match hex.get(..8) {
+        Some(prefix) if hex.len() > 8 => format!("{}…", prefix),
+        _ => hex.to_string(),
+    }
+}
+
+/// Prints a public key as plain hex in both Debug and Display, unlike
+/// `PublicKey`'s own Debug which shows the curve point
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct HexKey<'a>(pub &'a PublicKey);
+
+impl fmt::Debug for HexKey<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl fmt::Display for HexKey<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Number of leading zero bits of a hex-encoded hash
+pub fn leading_zero_bits(hash: &Hash) -> u32 {
+    let mut bits = 0;
+    for c in hash.chars() {
+        match c.to_digit(16) {
+            Some(0) => bits += 4,
+            Some(digit) => return bits + digit.leading_zeros() - 28,
+            None => break,
+        }
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::traits::{Hashable, WorldStateRead};
+    use crate::types::TransactionBuilder;
+
+    #[test]
+    fn test_bootstrap_chain() {
+        let (mut bc, keypairs) = bootstrap_chain(&[("alice", 100.into()), ("bob", 5.into())]);
+        assert_eq!(bc.len(), 1);
+        assert_eq!(bc.total_supply(), 105);
+        assert_eq!(keypairs.len(), 2);
+
+        let transfer = TransactionBuilder::new(TransactionData::Transfer {
+            to: "bob".to_string(),
+            amount: 30.into(),
+            fee: 0.into(),
+        })
+        .from("alice".to_string())
+        .sign(&keypairs["alice"], bc.network_id());
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.set_timestamp(1);
+        block.add_transaction(transfer);
+        block.set_state_root(bc.state_root_after(&block).unwrap());
+        bc.append_block(block).unwrap();
+
+        assert_eq!(bc.balance_of(&"alice".to_string()), Some(70.into()));
+        assert_eq!(bc.balance_of(&"bob".to_string()), Some(35.into()));
+        assert_eq!(bc.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_hex_key() {
+        let keypair = Keypair::generate(&mut StdRng::seed_from_u64(1));
+        let debug = format!("{:?}", HexKey(&keypair.public));
+
+        assert_eq!(debug.len(), 64);
+        assert!(debug.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(debug, hex::encode(keypair.public));
+        assert_eq!(HexKey(&keypair.public).to_string(), debug);
+    }
+
+    #[test]
+    fn test_amounts() {
+        assert_eq!(parse_amount("1.5"), Ok(150_000_000.into()));
+        assert_eq!(format_amount(150_000_000.into()), "1.5");
+        assert_eq!(parse_amount("0.00000001"), Ok(1.into()));
+        assert_eq!(format_amount(1.into()), "0.00000001");
+        assert_eq!(parse_amount("42"), Ok(4_200_000_000.into()));
+        assert_eq!(format_amount(4_200_000_000.into()), "42");
+        assert_eq!(format_amount(Balance::ZERO), "0");
+        assert_eq!(parse_amount(&format_amount(Balance::MAX)), Ok(Balance::MAX));
+
+        assert_eq!(
+            parse_amount("1.000000001"),
+            Err("Too many decimal places: 9 > 8".to_string())
+        );
+        for invalid in ["", ".5", "1.", "-1", "1.5.0", "1,5", " 1"] {
+            assert_eq!(
+                parse_amount(invalid),
+                Err(format!("Invalid amount: {:?}", invalid))
+            );
+        }
+        assert!(parse_amount(&u128::MAX.to_string())
+            .unwrap_err()
+            .starts_with("Amount is too large"));
+    }
+
+    #[test]
+    fn test_leading_zero_bits() {
+        assert_eq!(leading_zero_bits(&"f0".to_string()), 0);
+        assert_eq!(leading_zero_bits(&"7f".to_string()), 1);
+        assert_eq!(leading_zero_bits(&"01".to_string()), 7);
+        assert_eq!(leading_zero_bits(&"0000".to_string()), 16);
+    }
+
+    #[test]
+    fn test_seeded_rng() {
+        let account_id = generate_random_account_with_rng(&mut StdRng::seed_from_u64(7));
+        assert_eq!(
+            account_id,
+            generate_random_account_with_rng(&mut StdRng::seed_from_u64(7))
+        );
+        assert_ne!(
+            account_id,
+            generate_random_account_with_rng(&mut StdRng::seed_from_u64(8))
+        );
+
+        let tx = create_account_tx_with_rng(account_id.clone(), &mut StdRng::seed_from_u64(7));
+        assert_eq!(
+            tx.hash(),
+            create_account_tx_with_rng(account_id, &mut StdRng::seed_from_u64(7)).hash()
+        );
+    }
+
+    #[test]
+    fn test_short_hex() {
+        assert_eq!(short_hex("0123456789abcdef"), "01234567…");
+        assert_eq!(short_hex("01234567"), "01234567");
+        assert_eq!(short_hex(""), "");
+    }
+
+    #[test]
+    fn test_sign_hash() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let hash = create_account_tx("alice".to_string()).hash();
+        let signature = sign_hash(&keypair, "a", &hash);
+
+        assert!(verify_hash(&keypair.public, "a", &hash, &signature));
+        assert!(!verify_hash(
+            &keypair.public,
+            "a",
+            &create_account_tx("bob".to_string()).hash(),
+            &signature
+        ));
+        assert!(!verify_hash(
+            &Keypair::generate(&mut OsRng {}).public,
+            "a",
+            &hash,
+            &signature
+        ));
+    }
+
+    #[test]
+    fn test_sign_hash_bound_to_network() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let hash = create_account_tx("alice".to_string()).hash();
+        let signature = sign_hash(&keypair, "a", &hash);
+
+        assert!(!verify_hash(&keypair.public, "b", &hash, &signature));
+        assert!(!verify_hash(&keypair.public, "", &hash, &signature));
+        // The raw hash isn't what gets signed
+        let raw = keypair.sign(hash.as_bytes()).to_bytes();
+        assert!(!verify_hash(&keypair.public, "a", &hash, &raw));
+    }
+
+    #[test]
+    fn test_derive_account_id() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let public_key = PublicKey::from_bytes(keypair.public.as_bytes()).unwrap();
+
+        let account_id = derive_account_id(&keypair.public);
+        assert_eq!(account_id, derive_account_id(&public_key));
+        assert_eq!(account_id.len(), 64);
+        assert_ne!(
+            account_id,
+            derive_account_id(&Keypair::generate(&mut OsRng {}).public)
+        );
+    }
+}
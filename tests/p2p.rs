@@ -0,0 +1,93 @@
+#![cfg(feature = "p2p")]
+
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use blockchain::p2p::{read_message, request, Message, Node};
+use blockchain::types::{Block, Blockchain};
+use blockchain::utils::{create_account_tx, generate_random_account};
+
+fn start_node(blockchain: Blockchain) -> (Arc<Node>, Arc<Mutex<Blockchain>>) {
+    let blockchain = Arc::new(Mutex::new(blockchain));
+    let node = Arc::new(Node::bind("127.0.0.1:0", blockchain.clone()).unwrap());
+    let listener = node.clone();
+    thread::spawn(move || listener.run());
+    (node, blockchain)
+}
+
+#[test]
+fn test_block_gossip() {
+    let mut genesis = Blockchain::new();
+    let mut block = Block::new(None);
+    block.set_nonce(1);
+    block.add_transaction(create_account_tx("satoshi".to_string()));
+    genesis.append_block(block).unwrap();
+
+    let (miner, miner_chain) = start_node(genesis.clone());
+    let (peer, peer_chain) = start_node(genesis);
+    miner.add_peer(peer.local_addr().unwrap());
+
+    miner_chain
+        .lock()
+        .unwrap()
+        .add_to_pool(create_account_tx("alice".to_string()))
+        .unwrap();
+    let replies = miner.mine().unwrap();
+
+    assert!(matches!(replies.as_slice(), [(_, Message::Accepted)]));
+    assert_eq!(peer_chain.lock().unwrap().len(), 2);
+    assert_eq!(
+        peer_chain.lock().unwrap().get_last_block_hash(),
+        miner_chain.lock().unwrap().get_last_block_hash()
+    );
+
+    let peer_addr = peer.local_addr().unwrap();
+    match request(peer_addr, &Message::GetChain).unwrap() {
+        Message::Chain(blocks) => assert_eq!(blocks.len(), 2),
+        reply => panic!("Unexpected reply: {:?}", reply),
+    }
+}
+
+#[test]
+fn test_invalid_block_rejected() {
+    let (peer, peer_chain) = start_node(Blockchain::new());
+    let peer_addr = peer.local_addr().unwrap();
+
+    let mut block = Block::new(None);
+    block.set_nonce(1);
+    block.add_transaction(create_account_tx(generate_random_account()));
+    let mut tampered = serde_json::to_value(&block).unwrap();
+    tampered["hash"] = serde_json::Value::String("0".repeat(64));
+    let tampered: Block = serde_json::from_value(tampered).unwrap();
+    let mut orphan = Block::new(Some("unknown".to_string()));
+    orphan.add_transaction(create_account_tx(generate_random_account()));
+
+    assert!(matches!(
+        request(peer_addr, &Message::NewBlock(tampered)),
+        Ok(Message::Rejected(error)) if error == "Block has invalid hash"
+    ));
+    assert!(matches!(
+        request(peer_addr, &Message::NewBlock(orphan)),
+        Ok(Message::Rejected(error)) if error == "Block doesn't extend the head."
+    ));
+
+    // A peer that never sends anything doesn't hold up the others
+    let _silent = TcpStream::connect(peer_addr).unwrap();
+    assert!(matches!(
+        request(peer_addr, &Message::GetChain),
+        Ok(Message::Chain(blocks)) if blocks.is_empty()
+    ));
+
+    // A garbage frame only drops its own connection
+    let mut stream = TcpStream::connect(peer_addr).unwrap();
+    stream.write_all(&[0, 0, 0, 3, b'x', b'y', b'z']).unwrap();
+    assert!(read_message(&mut stream).is_err());
+
+    assert!(matches!(
+        request(peer_addr, &Message::NewBlock(block)),
+        Ok(Message::Accepted)
+    ));
+    assert_eq!(peer_chain.lock().unwrap().len(), 1);
+}
@@ -0,0 +1,154 @@
+#![cfg(feature = "server")]
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use blockchain::server::{Server, MAX_BODY};
+use blockchain::traits::{Hashable, WorldStateRead};
+use blockchain::types::{Block, Blockchain, Transaction, TransactionData};
+use blockchain::utils::sign_hash;
+use ed25519_dalek::Keypair;
+use rand::rngs::OsRng;
+
+fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        body.len(),
+        body
+    )
+    .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response[9..12].parse().unwrap();
+    let body = response
+        .split_once("\r\n\r\n")
+        .map(|(_, body)| body.to_string())
+        .unwrap_or_default();
+    (status, body)
+}
+
+fn genesis(keypair: &Keypair) -> Block {
+    let mut block = Block::new(None);
+    block.set_nonce(1);
+    block.add_transaction(Transaction::new(
+        TransactionData::CreateAccount("satoshi".to_string(), keypair.public),
+        None,
+    ));
+    block.add_transaction(Transaction::new(
+        TransactionData::CreateAccount(
+            "alice".to_string(),
+            Keypair::generate(&mut OsRng {}).public,
+        ),
+        None,
+    ));
+    block.add_transaction(Transaction::new(
+        TransactionData::MintInitialSupply {
+            to: "satoshi".to_string(),
+            amount: 100.into(),
+        },
+        None,
+    ));
+    block
+}
+
+#[test]
+fn test_submit_transfer_and_read_balance() {
+    let keypair = Keypair::generate(&mut OsRng {});
+    let mut bc = Blockchain::new();
+    bc.append_block(genesis(&keypair)).unwrap();
+    let genesis_hash = bc.get_last_block_hash().unwrap();
+    let network_id = bc.network_id().to_string();
+
+    let blockchain = Arc::new(Mutex::new(bc));
+    let server = Server::bind("127.0.0.1:0", blockchain.clone()).unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+
+    assert_eq!(
+        request(addr, "GET", "/height", ""),
+        (200, r#"{"height":1}"#.to_string())
+    );
+    let (status, body) = request(addr, "GET", &format!("/block/{}", genesis_hash), "");
+    assert_eq!(status, 200);
+    assert!(body.contains(&genesis_hash));
+    assert_eq!(request(addr, "GET", "/block/unknown", "").0, 404);
+
+    let mut tx = Transaction::new(
+        TransactionData::Transfer {
+            to: "alice".to_string(),
+            amount: 10.into(),
+            fee: 0.into(),
+        },
+        Some("satoshi".to_string()),
+    );
+    let mut forged_tx = tx.clone();
+    forged_tx.add_signature(sign_hash(
+        &Keypair::generate(&mut OsRng {}),
+        &network_id,
+        &tx.hash(),
+    ));
+    tx.add_signature(sign_hash(&keypair, &network_id, &tx.hash()));
+
+    assert_eq!(
+        request(
+            addr,
+            "POST",
+            "/tx",
+            &serde_json::to_string(&forged_tx).unwrap()
+        ),
+        (400, r#"{"error":"Invalid signature."}"#.to_string())
+    );
+    assert_eq!(
+        request(addr, "POST", "/tx", &serde_json::to_string(&tx).unwrap()),
+        (200, format!(r#"{{"hash":"{}"}}"#, tx.hash()))
+    );
+
+    blockchain
+        .lock()
+        .unwrap()
+        .mine_pending_transactions()
+        .unwrap();
+
+    assert_eq!(
+        request(addr, "GET", "/account/alice", ""),
+        (200, r#"{"balance":10,"account_type":"User"}"#.to_string())
+    );
+    assert_eq!(
+        request(addr, "GET", "/account/satoshi", ""),
+        (200, r#"{"balance":90,"account_type":"User"}"#.to_string())
+    );
+    assert_eq!(
+        request(addr, "GET", "/account/Alice", ""),
+        (200, r#"{"balance":10,"account_type":"User"}"#.to_string())
+    );
+    assert_eq!(request(addr, "GET", "/account/bob", "").0, 404);
+    assert_eq!(request(addr, "GET", "/account/bob!", "").0, 400);
+}
+
+#[test]
+fn test_body_too_large() {
+    let blockchain = Arc::new(Mutex::new(Blockchain::new()));
+    let server = Server::bind("127.0.0.1:0", blockchain).unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+
+    assert_eq!(
+        request(addr, "POST", "/tx", &" ".repeat(MAX_BODY + 1)),
+        (
+            413,
+            format!(
+                r#"{{"error":"Request body is larger than {} bytes"}}"#,
+                MAX_BODY
+            )
+        )
+    );
+    // A body right at the limit is read and parsed
+    assert_eq!(request(addr, "POST", "/tx", &" ".repeat(MAX_BODY)).0, 400);
+}